@@ -36,6 +36,13 @@ struct Cli {
     /// If provided, render the SVG as PNG and save to the given path
     #[clap(long, short, conflicts_with = "transpile_only")]
     png: Option<String>,
+
+    /// Load a grayscale PNG as a constant in the script, can be given multiple times
+    ///
+    /// The constant is an array of rows of pixels, where each pixel is a number
+    /// from 0 (black) to 255 (white), which can be passed to `heightmap()`
+    #[clap(long, value_name = "NAME=PATH")]
+    heightmap: Vec<String>,
 }
 
 fn main() -> ExitCode {
@@ -53,6 +60,15 @@ fn main() -> ExitCode {
             return ExitCode::FAILURE;
         }
     };
+    for heightmap in &args.heightmap {
+        match png::load_heightmap_const(heightmap) {
+            Ok(declaration) => transpiled_script.insert_str(0, &declaration),
+            Err(e) => {
+                eprintln!("Failed to load heightmap `{heightmap}`: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
     if let Some(command) = &args.command {
         transpiled_script.push('\n');
         if command.trim() == "-" {
@@ -2,7 +2,7 @@
 
 use std::path::Path;
 
-use anyhow::bail;
+use anyhow::{Context as _, bail};
 use prism_lib::Svg;
 use tiny_skia::Pixmap;
 use usvg::{Options, Transform, Tree};
@@ -18,3 +18,37 @@ pub fn save_svg_to_png(svg: &Svg, path: impl AsRef<Path>) -> anyhow::Result<()>
 
     Ok(())
 }
+
+/// Load a grayscale PNG from a `NAME=PATH` argument as a JS constant declaration
+/// of the pixel values (0 to 255) in rows
+pub fn load_heightmap_const(arg: &str) -> anyhow::Result<String> {
+    let Some((name, path)) = arg.split_once('=') else {
+        bail!("expected NAME=PATH");
+    };
+    let is_identifier = name.chars().enumerate().all(|(i, c)| {
+        c == '_' || c == '$' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+    });
+    if name.is_empty() || !is_identifier {
+        bail!("`{name}` is not a valid identifier");
+    }
+    let pixmap = Pixmap::load_png(path).with_context(|| format!("failed to load {path}"))?;
+    let rows: Vec<String> = pixmap
+        .pixels()
+        .chunks(pixmap.width() as usize)
+        .map(|row| {
+            let values: Vec<String> = row
+                .iter()
+                .map(|pixel| {
+                    // transparent pixels are black after demultiplying
+                    let color = pixel.demultiply();
+                    let luma = 299 * color.red() as u32
+                        + 587 * color.green() as u32
+                        + 114 * color.blue() as u32;
+                    ((luma + 500) / 1000).to_string()
+                })
+                .collect();
+            format!("[{}]", values.join(","))
+        })
+        .collect();
+    Ok(format!("const {name} = [{}];\n", rows.join(",")))
+}
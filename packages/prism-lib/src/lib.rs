@@ -4,6 +4,12 @@ mod math;
 /// Runtime shape management
 mod shape;
 
+/// Voxelization of generated shapes into prisms
+mod voxel;
+
+/// Bundled bitmap fonts for voxel text
+mod font;
+
 /// Polygon rendering
 mod render;
 
@@ -112,6 +112,17 @@ impl<T: Num + PartialOrd> Vec3<T> {
     }
 }
 
+impl Vec3<i32> {
+    /// Add the other vector, or None if any component is out of range
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Some(Self(
+            self.0.checked_add(other.0)?,
+            self.1.checked_add(other.1)?,
+            self.2.checked_add(other.2)?,
+        ))
+    }
+}
+
 /// Geometry in 3D space (position and size)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Geom3 {
@@ -136,6 +147,15 @@ impl Geom3 {
         }
     }
 
+    /// Create the prism, or None if the max corner is out of range
+    pub fn checked_new(pos: Vec3<i32>, size: Vec3<u32>) -> Option<Self> {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            pos.on(axis)
+                .checked_add(i32::try_from(size.on(axis)).ok()?)?;
+        }
+        Some(Self::new(pos, size))
+    }
+
     /// Returns the intersection of this prism with another prism
     ///
     /// If the prisms do not intersect, A 0-volume prism is returned.
@@ -267,6 +287,96 @@ impl Geom3 {
         self.pos.z() + self.size.z() as i32
     }
 
+    /// The end of the prism in the given direction
+    #[inline]
+    pub fn end(&self, axis: Axis) -> i32 {
+        self.pos.on(axis) + self.size.on(axis) as i32
+    }
+
+    /// Returns the prism rotated by quarter turns around the axis, at the pivot,
+    /// or None if the result is out of range
+    ///
+    /// Positive turns are counter-clockwise when looking from the positive
+    /// end of the axis (i.e. right-hand rule)
+    pub fn rotated(&self, axis: Axis, quarter_turns: u32, pivot: Vec3<i32>) -> Option<Self> {
+        // rotating from a to b
+        let (a, b) = axis.rotation_plane();
+        let (pa, pb) = (pivot.on(a), pivot.on(b));
+        let (a1, a2) = (self.pos.on(a), self.end(a));
+        let (b1, b2) = (self.pos.on(b), self.end(b));
+        let mut pos = self.pos;
+        let mut size = self.size;
+        match quarter_turns % 4 {
+            1 => {
+                // (a, b) -> (-b, a)
+                *pos.on_mut(a) = pa.checked_sub(b2.checked_sub(pb)?)?;
+                *pos.on_mut(b) = pb.checked_add(a1.checked_sub(pa)?)?;
+                *size.on_mut(a) = self.size.on(b);
+                *size.on_mut(b) = self.size.on(a);
+            }
+            2 => {
+                // (a, b) -> (-a, -b)
+                *pos.on_mut(a) = pa.checked_sub(a2.checked_sub(pa)?)?;
+                *pos.on_mut(b) = pb.checked_sub(b2.checked_sub(pb)?)?;
+            }
+            3 => {
+                // (a, b) -> (b, -a)
+                *pos.on_mut(a) = pa.checked_add(b1.checked_sub(pb)?)?;
+                *pos.on_mut(b) = pb.checked_sub(a2.checked_sub(pa)?)?;
+                *size.on_mut(a) = self.size.on(b);
+                *size.on_mut(b) = self.size.on(a);
+            }
+            _ => {}
+        }
+        Self::checked_new(pos, size)
+    }
+
+    /// Returns the prism reflected across the plane perpendicular
+    /// to the axis at the given position, or None if the result is out of range
+    pub fn mirrored(&self, axis: Axis, plane: i32) -> Option<Self> {
+        let mut pos = self.pos;
+        *pos.on_mut(axis) = plane.checked_mul(2)?.checked_sub(self.end(axis))?;
+        Self::checked_new(pos, self.size)
+    }
+
+    /// Returns the prism scaled by the factor on each axis, relative to the origin,
+    /// or None if the result is out of range
+    pub fn scaled(&self, factor: Vec3<u32>, origin: Vec3<i32>) -> Option<Self> {
+        let mut pos = self.pos;
+        let mut size = self.size;
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let f = i32::try_from(factor.on(axis)).ok()?;
+            let o = origin.on(axis);
+            *pos.on_mut(axis) = self
+                .pos
+                .on(axis)
+                .checked_sub(o)?
+                .checked_mul(f)?
+                .checked_add(o)?;
+            *size.on_mut(axis) = self.size.on(axis).checked_mul(factor.on(axis))?;
+        }
+        Self::checked_new(pos, size)
+    }
+
+    /// Returns the prism expanded by the amount in both directions on each axis,
+    /// or None if the result is out of range
+    pub fn expanded(&self, amount: Vec3<u32>) -> Option<Self> {
+        let mut pos = self.pos;
+        let mut size = self.size;
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let a = amount.on(axis);
+            *pos.on_mut(axis) = self.pos.on(axis).checked_sub(i32::try_from(a).ok()?)?;
+            *size.on_mut(axis) = a.checked_mul(2)?.checked_add(self.size.on(axis))?;
+        }
+        Self::checked_new(pos, size)
+    }
+
+    /// Number of unit cubes in the prism
+    #[inline]
+    pub fn volume(&self) -> u64 {
+        self.size.x() as u64 * self.size.y() as u64 * self.size.z() as u64
+    }
+
     /// Checks if the prism has positive volume
     #[inline]
     pub fn has_positive_volume(&self) -> bool {
@@ -306,6 +416,65 @@ impl Axis {
             _ => None,
         }
     }
+
+    /// Get the 2 other axes (a, b), such that a quarter turn
+    /// around this axis rotates a into b
+    pub fn rotation_plane(self) -> (Self, Self) {
+        match self {
+            Self::X => (Self::Y, Self::Z),
+            Self::Y => (Self::Z, Self::X),
+            Self::Z => (Self::X, Self::Y),
+        }
+    }
+}
+
+/// Number of grid cells per unit length on each axis
+///
+/// Shapes are stored as integer grid cells, while scripts use units.
+/// For example, at resolution 2, the coordinate 0.5 is the grid cell 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Resolution(pub u32);
+impl Resolution {
+    /// Max distance from a grid line for a coordinate to still be on it,
+    /// so decimal arithmetic like `0.1 * 3` doesn't fail
+    const TOLERANCE: f64 = 0.00001;
+
+    /// Convert a coordinate in units to grid cells, or None if it is
+    /// not on a grid line or out of range
+    pub fn to_grid(self, value: f64) -> Option<i32> {
+        let cells = value * self.0 as f64;
+        let whole = cells.round();
+        if (cells - whole).abs() > Self::TOLERANCE
+            || whole < i32::MIN as f64
+            || whole > i32::MAX as f64
+        {
+            return None;
+        }
+        Some(whole as i32)
+    }
+
+    /// Convert a non-negative length in units to grid cells, or None if it is
+    /// not on a grid line or out of range
+    pub fn to_grid_len(self, value: f64) -> Option<u32> {
+        let cells = value * self.0 as f64;
+        let whole = cells.round();
+        if (cells - whole).abs() > Self::TOLERANCE || whole < 0.0 || whole > u32::MAX as f64 {
+            return None;
+        }
+        Some(whole as u32)
+    }
+
+    /// Convert a length in units to grid cells without rounding
+    #[inline]
+    pub fn scale(self, value: f64) -> f64 {
+        value * self.0 as f64
+    }
+
+    /// Convert grid cells to units
+    #[inline]
+    pub fn to_units(self, cells: impl Into<f64>) -> f64 {
+        cells.into() / self.0 as f64
+    }
 }
 
 /// A 2D grid of (u, v) -> T
@@ -471,3 +640,101 @@ impl AtomicF64 {
         f64::from_bits(as_u64)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AXES: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    #[test]
+    fn rotated_quarter_turns() {
+        let prism = Geom3::new((1, 2, 3), (2, 3, 4));
+        let pivot = Vec3(0, 0, 0);
+        // (x, y) -> (-y, x)
+        assert_eq!(
+            prism.rotated(Axis::Z, 1, pivot),
+            Some(Geom3::new((-5, 1, 3), (3, 2, 4)))
+        );
+        assert_eq!(
+            prism.rotated(Axis::Z, 2, pivot),
+            Some(Geom3::new((-3, -5, 3), (2, 3, 4)))
+        );
+        for axis in AXES {
+            let mut rotated = prism;
+            for _ in 0..4 {
+                rotated = rotated.rotated(axis, 1, Vec3(7, -3, 5)).unwrap();
+                assert_eq!(rotated.volume(), prism.volume());
+            }
+            assert_eq!(rotated, prism);
+            assert_eq!(prism.rotated(axis, 3, pivot), {
+                let half = prism.rotated(axis, 2, pivot).unwrap();
+                half.rotated(axis, 1, pivot)
+            });
+        }
+    }
+
+    #[test]
+    fn rotated_out_of_range() {
+        let prism = Geom3::new((0, 0, 0), (1, 1, 1));
+        let pivot = Vec3(i32::MAX, i32::MIN, 0);
+        assert!(prism.rotated(Axis::Z, 1, pivot).is_none());
+        assert!(prism.rotated(Axis::Z, 2, pivot).is_none());
+        assert!(prism.rotated(Axis::Z, 3, pivot).is_none());
+        let far = Geom3::new((i32::MAX - 1, 0, 0), (1, 1, 1));
+        assert!(far.rotated(Axis::Z, 2, Vec3(0, 0, 0)).is_some());
+        assert!(far.rotated(Axis::Z, 2, Vec3(i32::MAX - 1, 0, 0)).is_some());
+        assert!(far.rotated(Axis::Z, 2, Vec3(i32::MAX, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn mirrored_twice_is_identity() {
+        let prism = Geom3::new((1, 2, 3), (2, 3, 4));
+        assert_eq!(
+            prism.mirrored(Axis::X, 0),
+            Some(Geom3::new((-3, 2, 3), (2, 3, 4)))
+        );
+        for axis in AXES {
+            let mirrored = prism.mirrored(axis, 10).unwrap();
+            assert_eq!(mirrored.mirrored(axis, 10), Some(prism));
+        }
+        assert!(prism.mirrored(Axis::X, 2_000_000_000).is_none());
+        assert!(prism.mirrored(Axis::X, i32::MIN / 2).is_none());
+    }
+
+    #[test]
+    fn scaled_and_expanded() {
+        let prism = Geom3::new((1, 2, 3), (2, 3, 4));
+        assert_eq!(
+            prism.scaled(Vec3(2, 1, 3), Vec3(1, 0, 0)),
+            Some(Geom3::new((1, 2, 9), (4, 3, 12)))
+        );
+        assert_eq!(
+            prism.expanded(Vec3(1, 0, 2)),
+            Some(Geom3::new((0, 2, 1), (4, 3, 8)))
+        );
+        let unit = Geom3::new((0, 0, 0), (2, 1, 1));
+        assert!(
+            unit.scaled(Vec3(3_000_000_000, 1, 1), Vec3(0, 0, 0))
+                .is_none()
+        );
+        assert!(unit.scaled(Vec3(1 << 30, 1, 1), Vec3(0, 0, 0)).is_none());
+        assert!(unit.scaled(Vec3(1 << 29, 1, 1), Vec3(0, 0, 0)).is_some());
+        assert!(unit.expanded(Vec3(3_000_000_000, 0, 0)).is_none());
+        assert!(unit.expanded(Vec3(1 << 30, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn checked_new_and_add() {
+        assert!(Geom3::checked_new(Vec3(i32::MAX, 0, 0), Vec3(0, 1, 1)).is_some());
+        assert!(Geom3::checked_new(Vec3(i32::MAX, 0, 0), Vec3(1, 1, 1)).is_none());
+        // sizes past i32::MAX can't be added to the position
+        assert!(Geom3::checked_new(Vec3(i32::MIN, 0, 0), Vec3(u32::MAX, 1, 1)).is_none());
+        assert!(Geom3::checked_new(Vec3(0, 0, 0), Vec3(1, 1, u32::MAX)).is_none());
+        assert_eq!(
+            Vec3(1, 2, 3).checked_add(Vec3(1, 1, 1)),
+            Some(Vec3(2, 3, 4))
+        );
+        assert_eq!(Vec3(1, i32::MAX, 3).checked_add(Vec3(1, 1, 1)), None);
+    }
+}
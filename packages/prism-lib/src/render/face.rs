@@ -1,110 +1,156 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::sync::Arc;
 
 use csscolorparser::Color;
 
 use super::Layer;
-use crate::math::{Entry, Grid2, Vec3, VecMap};
+use crate::math::{Entry, Geom3, Grid2, Vec3, VecMap};
 
-/// A unit face with a position, color and a direction
+/// Max number of unit faces a script color function can be called on
+/// in one render, to prevent the script from hanging on huge shapes
+pub const MAX_FACE_CALLBACKS: u64 = 1 << 20;
+
+/// Faces of a region at one depth with the same z, which are
+/// in a row on the grid
+///
+/// The faces are at `u = u1, u1 + 2, ..., u2` on the row `v`,
+/// and each face occupies the slots of the direction, see [`Side::slots`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FaceSpan {
+    v: i32,
+    u1: i32,
+    u2: i32,
+}
+
+impl FaceSpan {
+    /// Get the number of faces in the span
+    fn len(&self) -> usize {
+        ((self.u2 - self.u1) / 2 + 1) as usize
+    }
+}
+
+/// A rectangle of unit faces on the same plane, with the same color and direction
 ///
-/// Each face can be put onto a 2D grid, when faces of different
-/// positions are rendered into the same location on the grid,
-/// the face with higher `layer()` should be rendered on top
-/// (i.e. lower layer should be covered completely).
+/// Large shapes are rendered as regions, which are only broken into
+/// rows of faces one layer at a time when drawn onto the canvas
 #[derive(Debug, Clone, PartialEq)]
-pub struct Face {
-    /// Color of the face
-    pub color: Arc<Color>, // using reference since faces are created in bulk
-    /// Position of the unit cube that has this face
-    pub pos: Vec3<i32>,
-    /// Direction of the face on the unit cube
+pub struct FaceRegion {
+    /// Color of the faces
+    pub color: Arc<Color>,
+    /// Direction of the faces on the unit cubes
     pub dir: Side,
-
-    /// If the face is belongs to the back side (-X, -Y, -Z)
-    /// of a cube
+    /// The unit cubes that have the faces, 1 unit thick in the direction
+    pub cubes: Geom3,
+    /// If the faces belong to the back side (-X, -Y, -Z) of the unit cubes
     ///
     /// Back faces are on top of front faces
     is_back: bool,
+    /// Shader of the faces, or None to use the shader of the canvas
+    shader: Option<Arc<Vec3<Color>>>,
 }
 
-/// Sort the face by rendering order. Faces that should be
-/// rendered on top should be at the front of the list.
-pub fn sort_faces(faces: &mut [Face]) {
-    faces.sort_by_key(|face| std::cmp::Reverse(face.layer()));
-}
-
-impl Face {
-    /// Create a front face
-    pub fn front(color: &Arc<Color>, pos: impl Into<Vec3<i32>>) -> Self {
-        Self {
-            color: Arc::clone(color),
-            pos: pos.into(),
-            dir: Side::Front,
-            is_back: false,
-        }
-    }
-    /// Create a top face
-    pub fn top(color: &Arc<Color>, pos: impl Into<Vec3<i32>>) -> Self {
+impl FaceRegion {
+    pub fn new(color: &Arc<Color>, dir: Side, cubes: Geom3) -> Self {
         Self {
             color: Arc::clone(color),
-            pos: pos.into(),
-            dir: Side::Top,
+            dir,
+            cubes,
             is_back: false,
+            shader: None,
         }
     }
-    /// Create a side face
-    pub fn side(color: &Arc<Color>, pos: impl Into<Vec3<i32>>) -> Self {
-        Self {
-            color: Arc::clone(color),
-            pos: pos.into(),
-            dir: Side::Side,
-            is_back: false,
-        }
+    /// Shade the faces with the shader instead of the one on the canvas
+    pub fn with_shader(mut self, shader: Option<&Arc<Vec3<Color>>>) -> Self {
+        self.shader = shader.cloned();
+        self
     }
-    /// Turn this face into a back face
+    /// Turn the faces into back faces
     pub fn back(mut self) -> Self {
         self.is_back = true;
         self
     }
-    /// Get the rendering layer of the face
-    ///
-    /// Higher layer are rendered on top of lower layers
-    /// in the 2D grid.
-    pub fn layer(&self) -> i32 {
+
+    /// Get the range (inclusive) of `x + y + 2z` of the unit cubes,
+    /// which decides the layer of each face
+    fn depth_range(&self) -> (i32, i32) {
         // To make sense of the *2, consider moving
         // a face in +X direction by 1, then move it
         // in +Y direction by 1. This is equivalent to
         // moving it in +Z direction by 1. So
         // every movement in +Z direction is effectively moving
         // through 2 layers
-        let base = self.pos.x() + self.pos.y() + self.pos.z() * 2;
+        let c = &self.cubes;
+        let min = c.pos.x() + c.pos.y() + c.pos.z() * 2;
+        let max = (c.x_end() - 1) + (c.y_end() - 1) + (c.z_end() - 1) * 2;
+        (min, max)
+    }
 
+    /// Get the rendering layer of the faces at the depth
+    ///
+    /// Higher layer are rendered on top of lower layers
+    /// in the 2D grid.
+    fn layer(&self, depth: i32) -> i32 {
         // back faces are higher than front faces at the same unit cube
-        base * 2 + if self.is_back { 1 } else { 0 }
+        depth * 2 + if self.is_back { 1 } else { 0 }
     }
 
-    /// Get the UV coordinates of the face in the grid
-    ///
-    /// Each face occupies 2 slots in the grid. The returned
-    /// values are (u1, v1, u2, v2)
-    pub fn get_uvs(&self) -> (i32, i32, i32, i32) {
-        let ux = -self.pos.x();
-        let uy = self.pos.y();
-        let uz = 0;
-        let vx = self.pos.x();
-        let vy = self.pos.y();
-        let vz = -2 * self.pos.z();
-
-        let u = ux + uy + uz;
-        let v = vx + vy + vz;
-        match self.dir {
-            Side::Top => (u, v, u + 1, v),
-            Side::Front => (u, v + 1, u, v + 2),
-            Side::Side => (u + 1, v + 1, u + 1, v + 2),
+    /// Call f with the span of faces of the unit cubes where `x + y + 2z = depth`,
+    /// for each z that has any
+    fn for_each_span_at(&self, depth: i32, mut f: impl FnMut(FaceSpan)) {
+        let c = &self.cubes;
+        let (x1, y1) = (c.pos.x(), c.pos.y());
+        let (x2, y2) = (c.x_end() - 1, c.y_end() - 1);
+        // z where x + y = depth - 2z is possible
+        let z1 = c.pos.z().max((depth - x2 - y2 + 1).div_euclid(2));
+        let z2 = (c.z_end() - 1).min((depth - x1 - y1).div_euclid(2));
+        for z in z1..=z2 {
+            let sum = depth - z * 2;
+            let (xa, xb) = (x1.max(sum - y2), x2.min(sum - y1));
+            if xa > xb {
+                continue;
+            }
+            // the unit cube at (x, y, z) is at u = -x + y, v = x + y - 2z on the grid
+            f(FaceSpan {
+                v: sum - z * 2,
+                u1: sum - xb * 2,
+                u2: sum - xa * 2,
+            });
         }
     }
 }
 
+/// Colors of the faces of a shape in each direction
+///
+/// The colors are in the same order as the shader,
+/// i.e. x is the front, y is the side, and z is the top
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaceColors {
+    /// Colors of the faces in the positive directions
+    pub front: Vec3<Arc<Color>>,
+    /// Colors of the back faces (in the negative directions),
+    /// which are only visible through translucent faces
+    pub back: Vec3<Arc<Color>>,
+}
+
+impl FaceColors {
+    /// Get the color of the faces in the direction
+    pub fn get(&self, dir: Side, is_back: bool) -> &Arc<Color> {
+        let colors = if is_back { &self.back } else { &self.front };
+        match dir {
+            Side::Front => colors.x_ref(),
+            Side::Side => colors.y_ref(),
+            Side::Top => colors.z_ref(),
+        }
+    }
+    /// If any of the front faces can be seen through, in which case
+    /// the back faces need to be rendered
+    pub fn is_translucent(&self) -> bool {
+        let Vec3(x, y, z) = &self.front;
+        x.a < 1.0 || y.a < 1.0 || z.a < 1.0
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Side {
     /// The positive X direction
@@ -116,16 +162,33 @@ pub enum Side {
     Top,
 }
 
+impl Side {
+    /// Get the offsets of the 2 slots in the grid of a face in the direction,
+    /// from the (u, v) of the unit cube
+    fn slots(self) -> [(i32, i32); 2] {
+        match self {
+            Side::Top => [(0, 0), (1, 0)],
+            Side::Front => [(0, 1), (0, 2)],
+            Side::Side => [(1, 1), (1, 2)],
+        }
+    }
+}
+
 /// Grid for rendering faces
 #[derive(Debug, Clone)]
 pub struct Canvas {
-    /// The base shader in the config
+    /// The base shader in the config, for faces without their own shader
     ///
     /// The shaders are used here to shade the alpha-composited faces,
-    /// then passed to the next step to shade opaque and top-most alpha faces
+    /// and the shader colors of the opaque and top-most alpha faces
+    /// are kept to be shaded in the next step
     shader: Vec3<Color>,
     /// Direction of the face at each grid position
     grid: Grid2<CanvasPoint>,
+    /// Grid positions that already have an opaque color
+    opaque: OpaqueMask,
+    /// Number of faces skipped because they are completely hidden
+    culled: usize,
 }
 
 impl Canvas {
@@ -133,6 +196,8 @@ impl Canvas {
         Self {
             shader,
             grid: Grid2::new(),
+            opaque: OpaqueMask::default(),
+            culled: 0,
         }
     }
     pub fn set_shader(&mut self, shader: Vec3<Color>) {
@@ -140,40 +205,150 @@ impl Canvas {
     }
     /// Convert the rendered data into layers by color
     pub fn render_layers(&self) -> Vec<Layer> {
-        let mut builder = LayerBuilder::new(self.shader.clone());
+        let mut builder = LayerBuilder::new();
         for ((u, v), point) in self.grid.iter() {
             builder.render(*u, *v, point);
         }
         builder.build()
     }
+    /// Get the number of faces culled so far because they are hidden
+    /// behind opaque faces
+    pub fn culled(&self) -> usize {
+        self.culled
+    }
+
+    /// Render the face regions onto the canvas, from the highest layer to the lowest
+    ///
+    /// The regions are merged by layer without collecting all the faces.
+    /// Faces in the same layer never overlap unless they are the same face,
+    /// in which case the face from the earlier region is on top
+    pub fn render_regions(&mut self, regions: &[FaceRegion]) {
+        let base_shader = self.shader.clone();
+        // (layer, earlier region first, depth)
+        let mut heap: BinaryHeap<_> = regions
+            .iter()
+            .enumerate()
+            .map(|(i, region)| {
+                let (_, max) = region.depth_range();
+                (region.layer(max), Reverse(i), max)
+            })
+            .collect();
+        while let Some((_, Reverse(i), depth)) = heap.pop() {
+            let region = &regions[i];
+            let shader = region.shader.as_deref().unwrap_or(&base_shader);
+            let shade = shader_color(shader, region.dir);
+            region.for_each_span_at(depth, |span| {
+                self.render_span(&region.color, region.dir, shade, span);
+            });
+            if depth > region.depth_range().0 {
+                heap.push((region.layer(depth - 1), Reverse(i), depth - 1));
+            }
+        }
+    }
+
+    /// Render the faces in the span onto the canvas, without making each face
+    ///
+    /// The whole span is skipped if all of its slots are already opaque
+    fn render_span(&mut self, color: &Arc<Color>, dir: Side, shade: &Color, span: FaceSpan) {
+        let slots = dir.slots();
+        let hidden = slots.iter().all(|&(du, dv)| {
+            self.opaque
+                .contains_every_other(span.v + dv, span.u1 + du, span.u2 + du)
+        });
+        if hidden {
+            self.culled += span.len();
+            return;
+        }
+        let [(du1, dv1), (du2, dv2)] = slots;
+        for u in (span.u1..=span.u2).step_by(2) {
+            let slots = [(u + du1, span.v + dv1), (u + du2, span.v + dv2)];
+            self.render_slots(color, shade, slots);
+        }
+    }
 
-    /// Render one face onto the canvas
-    pub fn render_face(&mut self, face: &Face) {
-        let (u1, v1, u2, v2) = face.get_uvs();
-        self.render_face_at(face, u1, v1);
-        self.render_face_at(face, u2, v2);
+    /// Render the 2 slots of a face
+    ///
+    /// Since faces are rendered from the top layer down, a face is skipped
+    /// without touching the grid if both of its slots are already opaque
+    fn render_slots(&mut self, color: &Arc<Color>, shade: &Color, slots: [(i32, i32); 2]) {
+        if slots.iter().all(|&(u, v)| self.opaque.contains(u, v)) {
+            self.culled += 1;
+            return;
+        }
+        for (u, v) in slots {
+            self.render_face_at(color, shade, u, v);
+            if color.a >= 1.0 {
+                self.opaque.insert(u, v);
+            }
+        }
     }
 
-    fn render_face_at(&mut self, face: &Face, u: i32, v: i32) {
+    fn render_face_at(&mut self, color: &Arc<Color>, shade: &Color, u: i32, v: i32) {
         match self.grid.entry(u, v) {
             Entry::Occupied(mut point) => {
                 // if the grid already has a face above it,try
                 // to compose the color
-                point
-                    .get_mut()
-                    .add_color(face.dir, &face.color, &self.shader);
+                point.get_mut().add_color(color, shade);
             }
             Entry::Vacant(point) => {
-                point.insert(CanvasPoint::new(face.dir, &face.color));
+                point.insert(CanvasPoint::new(color, shade));
             }
         };
     }
 }
+
+/// Get the shader color for faces in the direction
+fn shader_color(shader: &Vec3<Color>, dir: Side) -> &Color {
+    match dir {
+        Side::Front => shader.x_ref(),
+        Side::Side => shader.y_ref(),
+        Side::Top => shader.z_ref(),
+    }
+}
+
+/// Bit set of grid positions, in 8x8 chunks
+///
+/// This is much cheaper to query than the canvas grid, and is used
+/// to cull faces that are hidden behind opaque faces
+#[derive(Debug, Clone, Default)]
+struct OpaqueMask(HashMap<(i32, i32), u64>);
+
+impl OpaqueMask {
+    /// Get the chunk key and the bit of (u, v) in the chunk
+    fn locate(u: i32, v: i32) -> ((i32, i32), u64) {
+        let bit = (v & 7) * 8 + (u & 7);
+        ((u >> 3, v >> 3), 1 << bit)
+    }
+    fn contains(&self, u: i32, v: i32) -> bool {
+        let (key, bit) = Self::locate(u, v);
+        self.0.get(&key).is_some_and(|bits| bits & bit != 0)
+    }
+    /// Check if every other position from `u1` to `u2` (inclusive) on the row `v`
+    /// is in the mask, checking 8 positions at a time
+    fn contains_every_other(&self, v: i32, u1: i32, u2: i32) -> bool {
+        let shift = (v & 7) * 8;
+        // chunks start at even u, so the positions have the same bits in each chunk
+        let every_other: u64 = if u1 & 1 == 0 { 0x55 } else { 0xaa };
+        for chunk_u in (u1 >> 3)..=(u2 >> 3) {
+            let lo = u1.max(chunk_u * 8) & 7;
+            let hi = u2.min(chunk_u * 8 + 7) & 7;
+            let needed = (((1 << (hi - lo + 1)) - 1) << lo) & every_other;
+            let bits = self.0.get(&(chunk_u, v >> 3)).copied().unwrap_or_default();
+            if (bits >> shift) & needed != needed {
+                return false;
+            }
+        }
+        true
+    }
+    fn insert(&mut self, u: i32, v: i32) {
+        let (key, bit) = Self::locate(u, v);
+        *self.0.entry(key).or_default() |= bit;
+    }
+}
+
 /// Render faces into 2D colors
 #[derive(Debug)]
 struct LayerBuilder {
-    /// The original shader colors
-    shader: Vec3<Color>,
     /// The opaque color layers
     opaque: VecMap<Layer>,
     /// Shaders for the opaque layers
@@ -185,9 +360,8 @@ struct LayerBuilder {
 }
 
 impl LayerBuilder {
-    pub fn new(shader: Vec3<Color>) -> Self {
+    pub fn new() -> Self {
         Self {
-            shader,
             opaque: VecMap::new(),
             opaque_shaders: VecMap::new(),
             alpha: VecMap::new(),
@@ -207,11 +381,7 @@ impl LayerBuilder {
             self.opaque.get_mut(&color).set(u, v, ());
 
             // set opaque shader
-            let shader_color = match point.opaque_face {
-                Side::Front => self.shader.x_ref(),
-                Side::Side => self.shader.y_ref(),
-                Side::Top => self.shader.z_ref(),
-            };
+            let shader_color = &point.opaque_shade;
             if shader_color.a > 0.0 {
                 let color = shader_color.into();
                 self.opaque_shaders.get_mut(&color).set(u, v, ());
@@ -222,11 +392,7 @@ impl LayerBuilder {
             self.alpha.get_mut(&color).set(u, v, ());
 
             // set alpha shader
-            let shader_color = match point.alpha_face {
-                Side::Front => self.shader.x_ref(),
-                Side::Side => self.shader.y_ref(),
-                Side::Top => self.shader.z_ref(),
-            };
+            let shader_color = &point.alpha_shade;
 
             // if the shader is transparent, we don't need to apply
             if shader_color.a > 0.0 {
@@ -253,14 +419,14 @@ impl LayerBuilder {
 
 #[derive(Debug, Clone)]
 pub struct CanvasPoint {
-    /// Direction of the face at this point on the opaque color.
+    /// Shader color of the face at this point on the opaque color.
     ///
     /// This is used to apply shading to the opaque layer
-    pub opaque_face: Side,
-    /// Direction of the top most alpha face at this point.
+    pub opaque_shade: Color,
+    /// Shader color of the top most alpha face at this point.
     ///
     /// This is used to apply shading to the alpha layer
-    pub alpha_face: Side,
+    pub alpha_shade: Color,
     /// Opaque color at the bottom of the layer
     pub opaque_color: Arc<Color>,
     /// The alpha-blended color
@@ -286,26 +452,26 @@ const TRANSPARENT: Color = Color {
 };
 
 impl CanvasPoint {
-    pub fn new(face: Side, color: &Arc<Color>) -> Self {
+    pub fn new(color: &Arc<Color>, shade: &Color) -> Self {
         if color.a < 1.0 {
             Self {
-                opaque_face: face,
-                alpha_face: face,
+                opaque_shade: TRANSPARENT,
+                alpha_shade: shade.clone(),
                 opaque_color: Arc::new(TRANSPARENT),
                 alpha_color: color.as_ref().clone(),
                 top_alpha: color.a,
             }
         } else {
             Self {
-                opaque_face: face,
-                alpha_face: face,
+                opaque_shade: shade.clone(),
+                alpha_shade: TRANSPARENT,
                 opaque_color: Arc::clone(color),
                 alpha_color: TRANSPARENT,
                 top_alpha: 0.0,
             }
         }
     }
-    pub fn add_color(&mut self, face: Side, color: &Arc<Color>, shader: &Vec3<Color>) {
+    pub fn add_color(&mut self, color: &Arc<Color>, shade: &Color) {
         // if self already has a base opaque color,
         // anything added below will be invisible
         if self.opaque_color.a >= 1.0 {
@@ -317,14 +483,9 @@ impl CanvasPoint {
                 return;
             }
             // blend with current color (self over shade over color)
-            let shader_color = match face {
-                Side::Front => shader.x_ref(),
-                Side::Side => shader.y_ref(),
-                Side::Top => shader.z_ref(),
-            };
-            if shader_color.a > 0.0 {
+            if shade.a > 0.0 {
                 // compose the shader's alpha value with the color's alpha value
-                let mut shader_color = shader_color.clone();
+                let mut shader_color = shade.clone();
                 shader_color.a *= color.a;
                 // then, blend with color
                 let temp = blend(&shader_color, color);
@@ -336,7 +497,7 @@ impl CanvasPoint {
             }
         } else {
             self.opaque_color = Arc::clone(color);
-            self.opaque_face = face;
+            self.opaque_shade = shade.clone();
         }
     }
 }
@@ -360,3 +521,67 @@ fn blend_component(a_part: f32, b_part: f32, alpha: f32, color_a: f32, color_b:
     let cb_part = color_b * b_part;
     (ca_part + cb_part) / alpha
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Arbitrary;
+
+    fn colors(a: f32) -> FaceColors {
+        let color = Arc::new(Color::new(1.0, 0.0, 0.0, a));
+        FaceColors {
+            front: Vec3(color.clone(), color.clone(), color.clone()),
+            back: Vec3(color.clone(), color.clone(), color),
+        }
+    }
+
+    fn shader() -> Vec3<Color> {
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        Vec3(white.clone(), white.clone(), white)
+    }
+
+    #[test]
+    fn culls_faces_behind_opaque_faces() {
+        let shape = Arbitrary::from_prism(Geom3::new((0, 0, 0), (2, 3, 4)));
+        let mut regions = Vec::new();
+        shape.render_regions(&colors(1.0), &mut regions);
+        let mut canvas = Canvas::new(shader());
+        canvas.render_regions(&regions);
+        assert_eq!(canvas.culled(), 0);
+        let layers = format!("{:?}", canvas.render_layers());
+        // the same faces again are completely hidden by the first ones
+        canvas.render_regions(&regions);
+        // top 2x3, front 3x4, side 2x4
+        assert_eq!(canvas.culled(), 6 + 12 + 8);
+        assert_eq!(format!("{:?}", canvas.render_layers()), layers);
+    }
+
+    #[test]
+    fn translucent_faces_are_not_culled() {
+        let shape = Arbitrary::from_prism(Geom3::new((0, 0, 0), (2, 2, 2)));
+        let mut regions = Vec::new();
+        shape.render_regions(&colors(0.5), &mut regions);
+        let mut canvas = Canvas::new(shader());
+        canvas.render_regions(&regions);
+        canvas.render_regions(&regions);
+        assert_eq!(canvas.culled(), 0);
+    }
+
+    #[test]
+    fn opaque_mask_every_other() {
+        let mut mask = OpaqueMask::default();
+        for u in (-9..=21).step_by(2) {
+            mask.insert(u, 3);
+        }
+        for u1 in -12..24 {
+            for u2 in u1..24 {
+                let expected = (u1..=u2).step_by(2).all(|u| mask.contains(u, 3));
+                assert_eq!(
+                    mask.contains_every_other(3, u1, u2),
+                    expected,
+                    "{u1}..={u2}"
+                );
+            }
+        }
+    }
+}
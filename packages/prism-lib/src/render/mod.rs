@@ -1,5 +1,5 @@
 /// Shapes broken down into faces, each with a unit size,
-/// a position, and a direction
+/// a position, and a direction, grouped into rectangle regions
 mod face;
 pub use face::*;
 
@@ -1,18 +1,26 @@
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 use boa_engine::object::builtins::JsArray;
 use boa_engine::{Context, JsArgs, JsError, JsResult, JsValue};
 use csscolorparser::{Color, ParseColorError};
 
-use crate::math::{AtomicF64, Vec3};
-use crate::render::{self, Canvas, Face, Layer};
+use crate::font::Font;
+use crate::math::{AtomicF64, Axis, Geom3, Resolution, Vec3};
+use crate::render::{Canvas, FaceColors, FaceRegion, Layer, MAX_FACE_CALLBACKS, Side};
 use crate::shape::ShapeVec;
+use crate::voxel;
 
 /// Builtin bindings for the rendering script engine
 pub struct Builtin {
     /// Unit length of the shape
     unit: Arc<AtomicF64>,
+    /// Grid cells per unit length
+    resolution: Arc<AtomicU32>,
+    /// If the unit length is for each grid cell instead of each unit,
+    /// for the deprecated `scale()`
+    unit_per_cell: Arc<AtomicBool>,
     /// The canvas for rendering
     canvas: Arc<RwLock<Canvas>>,
     /// Object ID counter for debugging
@@ -21,8 +29,8 @@ pub struct Builtin {
     logs: Arc<RwLock<Vec<String>>>,
     /// Shapes in the scene
     shapes: ShapeVec,
-    /// Rendered faces
-    faces: Arc<RwLock<Vec<Face>>>,
+    /// Rendered face regions
+    regions: Arc<RwLock<Vec<FaceRegion>>>,
 }
 const DEFAULT_SHADER_X: Color = Color {
     r: 0.0,
@@ -50,11 +58,13 @@ impl Default for Builtin {
         let shader = (DEFAULT_SHADER_X, DEFAULT_SHADER_Y, DEFAULT_SHADER_Z).into();
         Self {
             unit: Arc::new(AtomicF64::new(20.)),
+            resolution: Arc::new(AtomicU32::new(1)),
+            unit_per_cell: Arc::new(AtomicBool::new(false)),
             canvas: Arc::new(RwLock::new(Canvas::new(shader))),
             obj_id: Arc::new(AtomicU64::new(1)),
             logs: Arc::new(RwLock::new(Vec::new())),
             shapes: ShapeVec::default(),
-            faces: Arc::new(RwLock::new(Vec::new())),
+            regions: Arc::new(RwLock::new(Vec::new())),
         }
     }
 }
@@ -67,15 +77,9 @@ impl Builtin {
     /// with the 3D space properly
     pub fn render_layers(&self) -> Vec<Layer> {
         {
-            let mut faces = self.faces.write().unwrap();
-            render::sort_faces(&mut faces);
-        }
-        {
-            let faces = self.faces.read().unwrap();
+            let regions = self.regions.read().unwrap();
             let mut canvas = self.canvas.write().unwrap();
-            for face in faces.iter() {
-                canvas.render_face(face);
-            }
+            canvas.render_regions(&regions);
         }
         {
             let canvas = self.canvas.read().unwrap();
@@ -83,12 +87,22 @@ impl Builtin {
         }
     }
 
+    /// Get the number of hidden faces skipped when rendering into layers
+    pub fn get_culled(&self) -> usize {
+        self.canvas.read().unwrap().culled()
+    }
+
     pub fn get_logs(&self) -> Vec<String> {
         self.logs.read().unwrap().clone()
     }
 
+    /// Get the unit length of each grid cell for rendering
     pub fn get_unit(&self) -> f64 {
-        self.unit.load(Ordering::SeqCst)
+        let unit = self.unit.load(Ordering::SeqCst);
+        if self.unit_per_cell.load(Ordering::SeqCst) {
+            return unit;
+        }
+        unit / self.resolution.load(Ordering::SeqCst) as f64
     }
 }
 
@@ -135,6 +149,44 @@ macro_rules! arg_u32 {
     };
 }
 
+macro_rules! arg_f64 {
+    ($args:ident, $ctx:ident, $index:literal) => {
+        $args.get_or_undefined($index).to_number($ctx)
+    };
+}
+
+/// Read a coordinate in units as grid cells
+macro_rules! arg_coord {
+    ($args:ident, $res:ident, $ctx:ident, $index:literal) => {
+        arg_f64!($args, $ctx, $index).and_then(|v| {
+            $res.to_grid(v)
+                .ok_or_else(|| Error::OffGrid(v, $res.0).into_js())
+        })
+    };
+}
+
+/// Read a non-negative length in units as grid cells
+macro_rules! arg_len {
+    ($args:ident, $res:ident, $ctx:ident, $index:literal) => {
+        arg_f64!($args, $ctx, $index).and_then(|v| {
+            $res.to_grid_len(v)
+                .ok_or_else(|| Error::OffGrid(v, $res.0).into_js())
+        })
+    };
+}
+
+macro_rules! arg_radius {
+    ($args:ident, $ctx:ident, $index:literal) => {
+        arg_f64!($args, $ctx, $index).and_then(|r| {
+            if r.is_finite() && r >= 0.0 {
+                Ok(r)
+            } else {
+                Err(Error::InvalidRadius(r).into_js())
+            }
+        })
+    };
+}
+
 macro_rules! arg_shape {
     ($args:ident, $shapes:ident, $ctx:ident, $index:literal) => {
         $args.get_or_undefined($index).to_u32($ctx).and_then(|idx| {
@@ -145,6 +197,91 @@ macro_rules! arg_shape {
     };
 }
 
+/// Read an array of coordinates in units as grid cells
+macro_rules! arg_coords {
+    ($args:ident, $res:ident, $ctx:ident, $index:literal) => {
+        array_values($args.get_or_undefined($index), $ctx).and_then(|values| {
+            values
+                .iter()
+                .map(|v| {
+                    let v = v.to_number($ctx)?;
+                    $res.to_grid(v)
+                        .ok_or_else(|| Error::OffGrid(v, $res.0).into_js())
+                })
+                .collect::<JsResult<Vec<_>>>()
+        })
+    };
+}
+
+/// Read an array of strings
+macro_rules! arg_strings {
+    ($args:ident, $ctx:ident, $index:literal) => {
+        strings($args.get_or_undefined($index), $ctx)
+    };
+}
+
+/// Read the face colors as an array of 6 colors,
+/// the front, side and top colors, then the same for the back faces
+macro_rules! arg_face_colors {
+    ($args:ident, $ctx:ident, $index:literal) => {{
+        let colors = arg_strings!($args, $ctx, $index)?
+            .iter()
+            .map(|s| parse_color(s).map(Arc::new))
+            .collect::<JsResult<Vec<_>>>()?;
+        match <[_; 6]>::try_from(colors) {
+            Ok([x, y, z, back_x, back_y, back_z]) => Ok(FaceColors {
+                front: Vec3(x, y, z),
+                back: Vec3(back_x, back_y, back_z),
+            }),
+            Err(colors) => Err(Error::InvalidFaceColors(colors.len()).into_js()),
+        }
+    }};
+}
+
+/// Read the X/Y/Z shader colors, or None if undefined
+macro_rules! arg_shader {
+    ($args:ident, $ctx:ident, $index:literal) => {{
+        let value = $args.get_or_undefined($index);
+        if value.is_undefined() {
+            Ok(None)
+        } else {
+            match strings(value, $ctx)?.as_slice() {
+                [x, y, z] => parse_shader(x, y, z).map(|shader| Some(Arc::new(shader))),
+                colors => Err(Error::InvalidShader(colors.len()).into_js()),
+            }
+        }
+    }};
+}
+
+/// Read the marked characters for text shapes, or None if undefined
+macro_rules! arg_marks {
+    ($args:ident, $ctx:ident, $index:literal) => {{
+        let value = $args.get_or_undefined($index);
+        if value.is_undefined() {
+            Ok(None)
+        } else {
+            value.to_string($ctx).map(|s| Some(s.to_std_string_lossy()))
+        }
+    }};
+}
+
+/// Read an array of shape handles
+macro_rules! arg_shapes {
+    ($args:ident, $shapes:ident, $ctx:ident, $index:literal) => {
+        array_values($args.get_or_undefined($index), $ctx).and_then(|values| {
+            values
+                .iter()
+                .map(|v| {
+                    let idx = v.to_u32($ctx)?;
+                    $shapes
+                        .get(idx as usize)
+                        .ok_or_else(|| Error::InvalidShapeHandle(idx).into_js())
+                })
+                .collect::<JsResult<Vec<_>>>()
+        })
+    };
+}
+
 macro_rules! arg_axis {
     ($args:ident, $ctx:ident, $index:literal) => {
         $args.get_or_undefined($index).to_u32($ctx).and_then(|idx| {
@@ -153,6 +290,24 @@ macro_rules! arg_axis {
     };
 }
 
+/// Decode a bit mask of sides, where bit `axis * 2 + negative` is set
+/// for each side
+macro_rules! arg_sides {
+    ($args:ident, $ctx:ident, $index:literal) => {
+        $args.get_or_undefined($index).to_u32($ctx).map(|mask| {
+            [
+                $crate::math::Axis::X,
+                $crate::math::Axis::Y,
+                $crate::math::Axis::Z,
+            ]
+            .into_iter()
+            .flat_map(|axis| [(axis, false), (axis, true)])
+            .filter(|(axis, negative)| mask & (1 << (*axis as u32 * 2 + *negative as u32)) != 0)
+            .collect::<Vec<_>>()
+        })
+    };
+}
+
 impl Builtin {
     /// Bind the builtin functions to the JS engine
     pub fn bind_to_engine(&self, context: &mut Context) -> JsResult<()> {
@@ -174,33 +329,34 @@ impl Builtin {
                 Ok(JsValue::undefined())
             })?;
         }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            let unit_per_cell = Arc::clone(&self.unit_per_cell);
+            define_builtin!(context, "set_resolution", 2, |args, ctx| {
+                let value = arg_u32!(args, ctx, 0)?;
+                if value == 0 {
+                    return Err(Error::InvalidResolution(value).into_js());
+                }
+                let old = resolution.load(Ordering::SeqCst);
+                if value != old && !shapes.is_empty() {
+                    return Err(Error::ResolutionAfterShapes.into_js());
+                }
+                resolution.store(value, Ordering::SeqCst);
+                unit_per_cell.store(args.get_or_undefined(1).to_boolean(), Ordering::SeqCst);
+                Ok(JsValue::undefined())
+            })?;
+        }
         {
             let canvas = Arc::clone(&self.canvas);
             define_builtin!(context, "set_shader", 3, |args, ctx| {
                 let x = arg_string!(args, ctx, 0)?;
                 let y = arg_string!(args, ctx, 1)?;
                 let z = arg_string!(args, ctx, 2)?;
-
-                let x = if x.is_empty() {
-                    DEFAULT_SHADER_X
-                } else {
-                    parse_color(&x)?
-                };
-
-                let y = if y.is_empty() {
-                    DEFAULT_SHADER_Y
-                } else {
-                    parse_color(&y)?
-                };
-
-                let z = if z.is_empty() {
-                    DEFAULT_SHADER_Z
-                } else {
-                    parse_color(&z)?
-                };
+                let shader = parse_shader(&x, &y, &z)?;
 
                 let mut write = canvas.write().map_err(|e| JsError::from_rust(&e))?;
-                write.set_shader(Vec3(x, y, z));
+                write.set_shader(shader);
                 Ok(JsValue::undefined())
             })?;
         }
@@ -223,13 +379,15 @@ impl Builtin {
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_size", 1, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let size = shape.size();
                 let value = JsArray::from_iter(
                     [size.x(), size.y(), size.z()]
                         .into_iter()
-                        .map(JsValue::from),
+                        .map(|x| res.to_units(x).into()),
                     ctx,
                 );
                 Ok(value.into())
@@ -237,68 +395,352 @@ impl Builtin {
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_min", 2, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let axis = arg_axis!(args, ctx, 1)?;
                 let min = shape
                     .min(axis)
                     .ok_or_else(|| Error::MinOfEmptyShape.into_js())?;
-                Ok(min.into())
+                Ok(res.to_units(min).into())
             })?;
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_max", 2, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let axis = arg_axis!(args, ctx, 1)?;
-                let min = shape
+                let max = shape
                     .max(axis)
                     .ok_or_else(|| Error::MaxOfEmptyShape.into_js())?;
-                Ok(min.into())
+                Ok(res.to_units(max).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_is_empty", 1, |args, ctx| {
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                Ok(shape.is_empty().into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_volume", 1, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let cells_per_unit = (res.0 as f64).powi(3);
+                Ok((shape.volume() as f64 / cells_per_unit).into())
             })?;
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_contains", 4, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let point = (
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                    arg_coord!(args, res, ctx, 3)?,
+                );
+                Ok(shape.contains(point).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_intersects", 2, |args, ctx| {
+                let a = arg_shape!(args, shapes, ctx, 0)?;
+                let b = arg_shape!(args, shapes, ctx, 1)?;
+                Ok(a.intersects(&b).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_boxes", 1, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                // 6 numbers (x, y, z, dx, dy, dz) for each box
+                let value = JsArray::from_iter(
+                    shape.boxes().into_iter().flat_map(|b| {
+                        [
+                            res.to_units(b.pos.x()).into(),
+                            res.to_units(b.pos.y()).into(),
+                            res.to_units(b.pos.z()).into(),
+                            res.to_units(b.size.x()).into(),
+                            res.to_units(b.size.y()).into(),
+                            res.to_units(b.size.z()).into(),
+                        ]
+                    }),
+                    ctx,
+                );
+                Ok(value.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_at_point", 4, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let point = (
-                    arg_i32!(args, ctx, 1)?,
-                    arg_i32!(args, ctx, 2)?,
-                    arg_i32!(args, ctx, 3)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                    arg_coord!(args, res, ctx, 3)?,
                 );
                 Ok(shape.with_min(point).idx.into())
             })?;
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_at_axis_off", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let axis = arg_axis!(args, ctx, 1)?;
-                let offset = arg_i32!(args, ctx, 2)?;
+                let offset = arg_coord!(args, res, ctx, 2)?;
                 Ok(shape.with_axis_off(axis, offset).idx.into())
             })?;
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_translate", 4, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let offset = (
-                    arg_i32!(args, ctx, 1)?,
-                    arg_i32!(args, ctx, 2)?,
-                    arg_i32!(args, ctx, 3)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                    arg_coord!(args, res, ctx, 3)?,
                 );
                 Ok(shape.translate(offset).idx.into())
             })?;
         }
         {
             let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_translate_axis_off", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
                 let axis = arg_axis!(args, ctx, 1)?;
-                let offset = arg_i32!(args, ctx, 2)?;
+                let offset = arg_coord!(args, res, ctx, 2)?;
                 Ok(shape.translate_axis(axis, offset).idx.into())
             })?;
         }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_rotate", 3, |args, ctx| {
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let axis = arg_axis!(args, ctx, 1)?;
+                let turns = arg_i32!(args, ctx, 2)?.rem_euclid(4) as u32;
+                let rotated = shape
+                    .rotate(axis, turns, None)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(rotated.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_rotate_about", 6, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let axis = arg_axis!(args, ctx, 1)?;
+                let turns = arg_i32!(args, ctx, 2)?.rem_euclid(4) as u32;
+                let pivot = (
+                    arg_coord!(args, res, ctx, 3)?,
+                    arg_coord!(args, res, ctx, 4)?,
+                    arg_coord!(args, res, ctx, 5)?,
+                );
+                let rotated = shape
+                    .rotate(axis, turns, Some(pivot.into()))
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(rotated.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_mirror", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let axis = arg_axis!(args, ctx, 1)?;
+                let plane = arg_coord!(args, res, ctx, 2)?;
+                let mirrored = shape
+                    .mirror(axis, plane)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(mirrored.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_symmetric", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let axis = arg_axis!(args, ctx, 1)?;
+                let plane = arg_coord!(args, res, ctx, 2)?;
+                let symmetric = shape
+                    .symmetric(axis, plane)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(symmetric.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_scale", 4, |args, ctx| {
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let factor = (
+                    arg_u32!(args, ctx, 1)?,
+                    arg_u32!(args, ctx, 2)?,
+                    arg_u32!(args, ctx, 3)?,
+                );
+                let scaled = shape
+                    .scale(factor, None)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(scaled.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_scale_about", 7, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let factor = (
+                    arg_u32!(args, ctx, 1)?,
+                    arg_u32!(args, ctx, 2)?,
+                    arg_u32!(args, ctx, 3)?,
+                );
+                let origin = (
+                    arg_coord!(args, res, ctx, 4)?,
+                    arg_coord!(args, res, ctx, 5)?,
+                    arg_coord!(args, res, ctx, 6)?,
+                );
+                let scaled = shape
+                    .scale(factor, Some(origin.into()))
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(scaled.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_shell", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let thickness = arg_len!(args, res, ctx, 1)?;
+                let open = arg_sides!(args, ctx, 2)?;
+                let shell = shape
+                    .shell(thickness, &open)
+                    .ok_or_else(|| Error::TooLargeForGrid.into_js())?;
+                Ok(shell.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_filled", 1, |args, ctx| {
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let filled = shape
+                    .filled()
+                    .ok_or_else(|| Error::TooLargeForGrid.into_js())?;
+                Ok(filled.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_dilate", 4, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let amount = (
+                    arg_len!(args, res, ctx, 1)?,
+                    arg_len!(args, res, ctx, 2)?,
+                    arg_len!(args, res, ctx, 3)?,
+                );
+                let dilated = shape
+                    .dilate(amount)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(dilated.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_erode", 4, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let amount = (
+                    arg_len!(args, res, ctx, 1)?,
+                    arg_len!(args, res, ctx, 2)?,
+                    arg_len!(args, res, ctx, 3)?,
+                );
+                let eroded = shape
+                    .erode(amount)
+                    .ok_or_else(|| Error::TooLargeForGrid.into_js())?;
+                Ok(eroded.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_repeat", 5, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let offset = (
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                    arg_coord!(args, res, ctx, 3)?,
+                );
+                let count = arg_u32!(args, ctx, 4)?;
+                let repeated = shape
+                    .repeat(&[(offset.into(), count)])
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(repeated.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_repeat_grid", 9, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let offset_a = (
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                    arg_coord!(args, res, ctx, 3)?,
+                );
+                let count_a = arg_u32!(args, ctx, 4)?;
+                let offset_b = (
+                    arg_coord!(args, res, ctx, 5)?,
+                    arg_coord!(args, res, ctx, 6)?,
+                    arg_coord!(args, res, ctx, 7)?,
+                );
+                let count_b = arg_u32!(args, ctx, 8)?;
+                let repeated = shape
+                    .repeat(&[(offset_a.into(), count_a), (offset_b.into(), count_b)])
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(repeated.idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_components", 2, |args, ctx| {
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let connectivity = arg_u32!(args, ctx, 1)?;
+                let connectivity = voxel::Connectivity::from_u32(connectivity)
+                    .ok_or_else(|| Error::InvalidConnectivityEnum(connectivity).into_js())?;
+                let parts = shape
+                    .components(connectivity)
+                    .ok_or_else(|| Error::TooManyParts.into_js())?;
+                let value = JsArray::from_iter(parts.into_iter().map(|s| s.idx.into()), ctx);
+                Ok(value.into())
+            })?;
+        }
         {
             let shapes = self.shapes.clone();
             define_builtin!(context, "shape_union", 2, |args, ctx| {
@@ -325,29 +767,383 @@ impl Builtin {
         }
         {
             let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_xor", 2, |args, ctx| {
+                let a = arg_shape!(args, shapes, ctx, 0)?;
+                let b = arg_shape!(args, shapes, ctx, 1)?;
+                Ok(a.xor(&b).idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_union_all", 1, |args, ctx| {
+                let all = arg_shapes!(args, shapes, ctx, 0)?;
+                let idx = match all.split_first() {
+                    Some((first, rest)) => first.union_all(rest).idx,
+                    None => 0,
+                };
+                Ok(idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            define_builtin!(context, "shape_intersection_all", 1, |args, ctx| {
+                let all = arg_shapes!(args, shapes, ctx, 0)?;
+                let idx = match all.split_first() {
+                    Some((first, rest)) => first.intersection_all(rest).idx,
+                    None => 0,
+                };
+                Ok(idx.into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
             define_builtin!(context, "shape_from_prism", 6, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let pos = (
-                    arg_i32!(args, ctx, 0)?,
-                    arg_i32!(args, ctx, 1)?,
-                    arg_i32!(args, ctx, 2)?,
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
                 );
                 let size = (
-                    arg_u32!(args, ctx, 3)?,
-                    arg_u32!(args, ctx, 4)?,
-                    arg_u32!(args, ctx, 5)?,
+                    arg_len!(args, res, ctx, 3)?,
+                    arg_len!(args, res, ctx, 4)?,
+                    arg_len!(args, res, ctx, 5)?,
                 );
                 Ok(shapes.add_prism(pos, size).into())
             })?;
         }
         {
             let shapes = self.shapes.clone();
-            let faces = Arc::clone(&self.faces);
-            define_builtin!(context, "render", 2, |args, ctx| {
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_text3d", 7, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let origin = Vec3(
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let text = arg_string!(args, ctx, 3)?;
+                let axis = arg_axis!(args, ctx, 4)?;
+                let depth = arg_len!(args, res, ctx, 5)?;
+                let name = arg_string!(args, ctx, 6)?;
+                let font =
+                    Font::by_name(&name).ok_or_else(|| Error::UnknownFont(name).into_js())?;
+                // check the laid out text fits before laying it out
+                let (columns, rows) = font.extent(&text);
+                let (columns, rows) = match (u32::try_from(columns), u32::try_from(rows)) {
+                    (Ok(columns), Ok(rows)) => (columns, rows),
+                    _ => return Err(Error::ShapeTooLarge.into_js()),
+                };
+                let size = match axis {
+                    Axis::X => Vec3(depth, columns, rows),
+                    Axis::Y => Vec3(columns, depth, rows),
+                    Axis::Z => Vec3(columns, rows, depth),
+                };
+                if Geom3::checked_new(origin, size).is_none() {
+                    return Err(Error::ShapeTooLarge.into_js());
+                }
+                let prisms = voxel::extrude(&font.rows(&text), Some("#"), origin, axis, depth)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_ellipsoid", 6, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let center = (
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let radii = (
+                    res.scale(arg_radius!(args, ctx, 3)?),
+                    res.scale(arg_radius!(args, ctx, 4)?),
+                    res.scale(arg_radius!(args, ctx, 5)?),
+                );
+                let prisms = voxel::ellipsoid(center.into(), radii.into())
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_cylinder", 7, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let base = (
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let radius = res.scale(arg_radius!(args, ctx, 3)?);
+                let height = arg_len!(args, res, ctx, 4)?;
+                let axis = arg_axis!(args, ctx, 5)?;
+                let negative = args.get_or_undefined(6).to_boolean();
+                let prisms = voxel::cylinder(base.into(), radius, height, axis, negative)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_voxelize", 7, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let pos = (
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let size = (
+                    arg_len!(args, res, ctx, 3)?,
+                    arg_len!(args, res, ctx, 4)?,
+                    arg_len!(args, res, ctx, 5)?,
+                );
+                let volume = size.0 as u64 * size.1 as u64 * size.2 as u64;
+                if volume > voxel::MAX_PREDICATE_CUBES {
+                    return Err(Error::PredicateTooLarge(volume).into_js());
+                }
+                let predicate = args
+                    .get_or_undefined(6)
+                    .as_callable()
+                    .ok_or_else(|| Error::NotCallable.into_js())?;
+                let bound = Geom3::checked_new(pos.into(), size.into())
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                let prisms = voxel::from_predicate(bound, |x, y, z| {
+                    let point = [x, y, z].map(|v| res.to_units(v).into());
+                    predicate
+                        .call(&JsValue::undefined(), &point, ctx)
+                        .map(|v| v.to_boolean())
+                })?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_stairs", 9, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let origin = (
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let axis = horizontal(arg_axis!(args, ctx, 3)?)?;
+                let negative = args.get_or_undefined(4).to_boolean();
+                let steps = arg_u32!(args, ctx, 5)?;
+                let step = (
+                    arg_len!(args, res, ctx, 6)?,
+                    arg_len!(args, res, ctx, 7)?,
+                    arg_len!(args, res, ctx, 8)?,
+                );
+                let shape = voxel::stairs(origin.into(), axis, negative, steps, step.into())
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_arbitrary(shape).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_pyramid", 7, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let pos = (
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let size = (
+                    arg_len!(args, res, ctx, 3)?,
+                    arg_len!(args, res, ctx, 4)?,
+                    arg_len!(args, res, ctx, 5)?,
+                );
+                let levels = arg_u32!(args, ctx, 6)?;
+                let bound = Geom3::checked_new(pos.into(), size.into())
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                let shape =
+                    voxel::pyramid(bound, levels).ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_arbitrary(shape).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_wedge", 8, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let pos = (
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let size = (
+                    arg_len!(args, res, ctx, 3)?,
+                    arg_len!(args, res, ctx, 4)?,
+                    arg_len!(args, res, ctx, 5)?,
+                );
+                let axis = horizontal(arg_axis!(args, ctx, 6)?)?;
+                let negative = args.get_or_undefined(7).to_boolean();
+                let bound = Geom3::checked_new(pos.into(), size.into())
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                let shape = voxel::wedge(bound, axis, negative)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_arbitrary(shape).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_path", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let coords = arg_coords!(args, res, ctx, 0)?;
+                let chunks = coords.chunks_exact(3);
+                if !chunks.remainder().is_empty() {
+                    return Err(Error::InvalidPathCoords(coords.len()).into_js());
+                }
+                let points: Vec<_> = chunks.map(|c| Vec3(c[0], c[1], c[2])).collect();
+                let thickness = arg_len!(args, res, ctx, 1)?;
+                let closed = args.get_or_undefined(2).to_boolean();
+                let prisms = voxel::path(&points, closed, thickness)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_heightmap", 6, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let origin = Vec3(
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let scale = res.scale(arg_f64!(args, ctx, 4)?);
+                let base = arg_coord!(args, res, ctx, 5)? as f64;
+                let grid = array_values(args.get_or_undefined(3), ctx)?
+                    .iter()
+                    .map(|row| {
+                        array_values(row, ctx)?
+                            .iter()
+                            .map(|v| {
+                                // NaN and negative heights become 0
+                                let h = (base + (v.to_number(ctx)? * scale).round()).max(0.0);
+                                Ok(h.min(u32::MAX as f64) as u32)
+                            })
+                            .collect::<JsResult<Vec<_>>>()
+                    })
+                    .collect::<JsResult<Vec<_>>>()?;
+                let prisms = voxel::heightmap(origin, &grid)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_extrude", 7, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let origin = Vec3(
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let rows = arg_strings!(args, ctx, 3)?;
+                let axis = arg_axis!(args, ctx, 4)?;
+                let depth = arg_len!(args, res, ctx, 5)?;
+                let marks = arg_marks!(args, ctx, 6)?;
+                let prisms = voxel::extrude(&rows, marks.as_deref(), origin, axis, depth)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "shape_layers", 5, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
+                let origin = Vec3(
+                    arg_coord!(args, res, ctx, 0)?,
+                    arg_coord!(args, res, ctx, 1)?,
+                    arg_coord!(args, res, ctx, 2)?,
+                );
+                let layers = array_values(args.get_or_undefined(3), ctx)?
+                    .iter()
+                    .map(|layer| strings(layer, ctx))
+                    .collect::<JsResult<Vec<_>>>()?;
+                let marks = arg_marks!(args, ctx, 4)?;
+                let prisms = voxel::text_layers(&layers, marks.as_deref(), origin)
+                    .ok_or_else(|| Error::ShapeTooLarge.into_js())?;
+                Ok(shapes.add_prisms(prisms).into())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let regions = Arc::clone(&self.regions);
+            define_builtin!(context, "render", 3, |args, ctx| {
+                let shape = arg_shape!(args, shapes, ctx, 0)?;
+                let colors = arg_face_colors!(args, ctx, 1)?;
+                let shader = arg_shader!(args, ctx, 2)?;
+                let new_regions = shape.render(&colors);
+                let mut write = regions.write().map_err(|e| JsError::from_rust(&e))?;
+                write.extend(
+                    new_regions
+                        .into_iter()
+                        .map(|r| r.with_shader(shader.as_ref())),
+                );
+
+                Ok(JsValue::undefined())
+            })?;
+        }
+        {
+            let shapes = self.shapes.clone();
+            let regions = Arc::clone(&self.regions);
+            let resolution = Arc::clone(&self.resolution);
+            define_builtin!(context, "render_with", 3, |args, ctx| {
+                let res = Resolution(resolution.load(Ordering::SeqCst));
                 let shape = arg_shape!(args, shapes, ctx, 0)?;
-                let color = parse_color(&arg_string!(args, ctx, 1)?)?;
-                let new_faces = shape.render(color);
-                let mut write = faces.write().map_err(|e| JsError::from_rust(&e))?;
-                write.extend(new_faces);
+                let color_fn = args
+                    .get_or_undefined(1)
+                    .as_callable()
+                    .ok_or_else(|| Error::NotCallable.into_js())?;
+                let shader = arg_shader!(args, ctx, 2)?;
+                // parsed colors by the returned string, so repeated colors
+                // share the same Arc and can be merged into regions
+                let mut colors: HashMap<String, Arc<Color>> = HashMap::new();
+                let mut calls = 0;
+                let new_regions = shape.render_with(|pos, dir, is_back| {
+                    calls += 1;
+                    if calls > MAX_FACE_CALLBACKS {
+                        return Err(Error::TooManyFaces.into_js());
+                    }
+                    let side = match dir {
+                        Side::Front => 0,
+                        Side::Side => 1,
+                        Side::Top => 2,
+                    };
+                    let [x, y, z] = [pos.x(), pos.y(), pos.z()].map(|v| res.to_units(v).into());
+                    let color = color_fn
+                        .call(
+                            &JsValue::undefined(),
+                            &[x, y, z, side.into(), is_back.into()],
+                            ctx,
+                        )?
+                        .to_string(ctx)?
+                        .to_std_string_lossy();
+                    if let Some(color) = colors.get(&color) {
+                        return Ok(Arc::clone(color));
+                    }
+                    let parsed = Arc::new(parse_color(&color)?);
+                    colors.insert(color, Arc::clone(&parsed));
+                    Ok(parsed)
+                })?;
+                let mut write = regions.write().map_err(|e| JsError::from_rust(&e))?;
+                write.extend(
+                    new_regions
+                        .into_iter()
+                        .map(|r| r.with_shader(shader.as_ref())),
+                );
 
                 Ok(JsValue::undefined())
             })?;
@@ -357,10 +1153,56 @@ impl Builtin {
     }
 }
 
+/// Get the elements of a JS array
+fn array_values(value: &JsValue, ctx: &mut Context) -> JsResult<Vec<JsValue>> {
+    let obj = value.as_object().ok_or_else(|| Error::NotArray.into_js())?;
+    let array = JsArray::from_object(obj.clone())?;
+    let len = array.length(ctx)?;
+    (0..len).map(|i| array.get(i, ctx)).collect()
+}
+
+/// Get the elements of a JS array as strings
+fn strings(value: &JsValue, ctx: &mut Context) -> JsResult<Vec<String>> {
+    array_values(value, ctx)?
+        .iter()
+        .map(|v| v.to_string(ctx).map(|s| s.to_std_string_lossy()))
+        .collect()
+}
+
+fn horizontal(axis: Axis) -> Result<Axis, JsError> {
+    match axis {
+        Axis::Z => Err(Error::NotHorizontal.into_js()),
+        _ => Ok(axis),
+    }
+}
+
 fn parse_color(s: &str) -> Result<Color, JsError> {
     s.parse().map_err(|e| Error::InvalidColor(e).into_js())
 }
 
+/// Parse the X/Y/Z shader colors, where empty strings are the default colors
+fn parse_shader(x: &str, y: &str, z: &str) -> Result<Vec3<Color>, JsError> {
+    let x = if x.is_empty() {
+        DEFAULT_SHADER_X
+    } else {
+        parse_color(x)?
+    };
+
+    let y = if y.is_empty() {
+        DEFAULT_SHADER_Y
+    } else {
+        parse_color(y)?
+    };
+
+    let z = if z.is_empty() {
+        DEFAULT_SHADER_Z
+    } else {
+        parse_color(z)?
+    };
+
+    Ok(Vec3(x, y, z))
+}
+
 /// Error thrown to the JS side if something happens
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -372,6 +1214,49 @@ pub enum Error {
     MinOfEmptyShape,
     #[error("native: cannot access max of a shape with 0 volume")]
     MaxOfEmptyShape,
+    #[error("native: invalid radius: {0}")]
+    InvalidRadius(f64),
+    #[error("native: shape is too large to generate")]
+    ShapeTooLarge,
+    #[error(
+        "native: cannot call predicate on {0} unit cubes, the max is {max}",
+        max = voxel::MAX_PREDICATE_CUBES
+    )]
+    PredicateTooLarge(u64),
+    #[error(
+        "native: shape has too many unit faces for a color function, the max is {max}",
+        max = MAX_FACE_CALLBACKS
+    )]
+    TooManyFaces,
+    #[error("native: expected a horizontal direction (x or y)")]
+    NotHorizontal,
+    #[error("native: shape is too large for this operation")]
+    TooLargeForGrid,
+    #[error("native: expected a function")]
+    NotCallable,
+    #[error("native: expected an array")]
+    NotArray,
+    #[error("native: invalid connectivity enum: {0}")]
+    InvalidConnectivityEnum(u32),
+    #[error(
+        "native: shape has too many parts, the max is {max}",
+        max = voxel::MAX_PARTS
+    )]
+    TooManyParts,
+    #[error("native: unknown font: {0}")]
+    UnknownFont(String),
+    #[error("native: {0} is not on the grid at resolution {1}, or is out of range")]
+    OffGrid(f64, u32),
+    #[error("native: invalid resolution: {0}")]
+    InvalidResolution(u32),
+    #[error("native: resolution cannot change after shapes are created")]
+    ResolutionAfterShapes,
+    #[error("native: expected path coordinates in groups of 3, got {0}")]
+    InvalidPathCoords(usize),
+    #[error("native: expected 6 face colors, got {0}")]
+    InvalidFaceColors(usize),
+    #[error("native: expected 3 shader colors, got {0}")]
+    InvalidShader(usize),
     #[error("native: invalid color: {0}")]
     InvalidColor(#[from] ParseColorError),
 }
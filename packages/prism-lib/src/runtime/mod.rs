@@ -36,6 +36,7 @@ pub fn execute_script(source: &str) -> ScriptResult {
         unit,
         has_js_error,
         layers,
+        culled: binding.get_culled(),
         messages,
     }
 }
@@ -55,6 +56,8 @@ pub struct ScriptResult {
     pub has_js_error: bool,
     /// The rendering result
     pub layers: Vec<Layer>,
+    /// Number of faces culled because they are hidden behind opaque faces
+    pub culled: usize,
     /// The debug and error messages
     pub messages: Vec<String>,
 }
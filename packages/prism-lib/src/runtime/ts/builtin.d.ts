@@ -1,10 +1,18 @@
 // builtin bindings
+// coordinates and lengths are in units, and converted to grid cells natively
 
 declare type i32 = number;
 declare type u32 = number;
 declare type AxisEnum = 0 | 1 | 2;
+/** face, edge, vertex */
+declare type ConnectivityEnum = 0 | 1 | 2;
 declare function __builtin_log(msg: string): void;
 declare function __builtin_set_unit(unit: number): void;
+/**
+ * if unit_per_cell is true, the unit length is for each grid cell instead of each unit.
+ * Throws if any shape has been created
+ */
+declare function __builtin_set_resolution(resolution: u32, unit_per_cell: boolean): void;
 declare function __builtin_set_shader(x: string, y: string, z: string): void;
 declare function __builtin_debug(): void;
 declare function __builtin_nextid(): number;
@@ -12,13 +20,52 @@ declare function __builtin_nextid(): number;
 declare function __builtin_shape_size(idx: number): [number, number, number];
 declare function __builtin_shape_min(idx: number, axis: AxisEnum): number;
 declare function __builtin_shape_max(idx: number, axis: AxisEnum): number;
+declare function __builtin_shape_is_empty(idx: number): boolean;
+declare function __builtin_shape_volume(idx: number): number;
+declare function __builtin_shape_contains(idx: number, x: i32, y: i32, z: i32): boolean;
+declare function __builtin_shape_intersects(idx_a: number, idx_b: number): boolean;
+/** 6 numbers (x, y, z, dx, dy, dz) for each box */
+declare function __builtin_shape_boxes(idx: number): number[];
 declare function __builtin_shape_at_point(idx: number, x: i32, y: i32, z: i32): number;
 declare function __builtin_shape_at_axis_off(idx: number, axis: AxisEnum, offset: i32): number;
 declare function __builtin_shape_translate(idx: number, x: i32, y: i32, z: i32): number;
 declare function __builtin_shape_translate_axis_off(idx: number, axis: AxisEnum, offset: i32): number;
+declare function __builtin_shape_rotate(idx: number, axis: AxisEnum, turns: i32): number;
+declare function __builtin_shape_rotate_about(idx: number, axis: AxisEnum, turns: i32, x: i32, y: i32, z: i32): number;
+declare function __builtin_shape_mirror(idx: number, axis: AxisEnum, plane: i32): number;
+declare function __builtin_shape_symmetric(idx: number, axis: AxisEnum, plane: i32): number;
+declare function __builtin_shape_scale(idx: number, x: u32, y: u32, z: u32): number;
+declare function __builtin_shape_scale_about(idx: number, x: u32, y: u32, z: u32, ox: i32, oy: i32, oz: i32): number;
+/** open is a bit mask, where bit (axis * 2 + negative) is set for each open side */
+declare function __builtin_shape_shell(idx: number, thickness: u32, open: u32): number;
+declare function __builtin_shape_filled(idx: number): number;
+declare function __builtin_shape_dilate(idx: number, x: u32, y: u32, z: u32): number;
+declare function __builtin_shape_erode(idx: number, x: u32, y: u32, z: u32): number;
+declare function __builtin_shape_repeat(idx: number, x: i32, y: i32, z: i32, count: u32): number;
+declare function __builtin_shape_repeat_grid(idx: number, ax: i32, ay: i32, az: i32, count_a: u32, bx: i32, by: i32, bz: i32, count_b: u32): number;
+declare function __builtin_shape_components(idx: number, connectivity: ConnectivityEnum): number[];
 declare function __builtin_shape_union(idx_a: number, idx_b: number): number;
 declare function __builtin_shape_intersection(idx_a: number, idx_b: number): number;
 declare function __builtin_shape_difference(idx_a: number, idx_b: number): number;
+declare function __builtin_shape_xor(idx_a: number, idx_b: number): number;
+declare function __builtin_shape_union_all(idxs: number[]): number;
+declare function __builtin_shape_intersection_all(idxs: number[]): number;
 declare function __builtin_shape_from_prism(x: i32, y: i32, z: i32, dx: u32, dy: u32, dz: u32): number;
+declare function __builtin_shape_text3d(x: i32, y: i32, z: i32, text: string, axis: AxisEnum, depth: u32, font: string): number;
+declare function __builtin_shape_ellipsoid(x: i32, y: i32, z: i32, rx: number, ry: number, rz: number): number;
+declare function __builtin_shape_cylinder(x: i32, y: i32, z: i32, r: number, height: u32, axis: AxisEnum, negative: boolean): number;
+declare function __builtin_shape_voxelize(x: i32, y: i32, z: i32, dx: u32, dy: u32, dz: u32, predicate: (x: i32, y: i32, z: i32) => boolean): number;
+declare function __builtin_shape_stairs(x: i32, y: i32, z: i32, axis: AxisEnum, negative: boolean, steps: u32, dx: u32, dy: u32, dz: u32): number;
+declare function __builtin_shape_pyramid(x: i32, y: i32, z: i32, dx: u32, dy: u32, dz: u32, levels: u32): number;
+declare function __builtin_shape_wedge(x: i32, y: i32, z: i32, dx: u32, dy: u32, dz: u32, axis: AxisEnum, negative: boolean): number;
+/** points are 3 numbers (x, y, z) each */
+declare function __builtin_shape_path(points: i32[], thickness: u32, closed: boolean): number;
+/** height of each cell is base + round(grid[row][col] * scale) */
+declare function __builtin_shape_heightmap(x: i32, y: i32, z: i32, grid: number[][], scale: number, base: i32): number;
+declare function __builtin_shape_extrude(x: i32, y: i32, z: i32, rows: string[], axis: AxisEnum, depth: u32, marks: string | undefined): number;
+declare function __builtin_shape_layers(x: i32, y: i32, z: i32, layers: string[][], marks: string | undefined): number;
 
-declare function __builtin_render(idx: number, color: string): void;
+/** colors are front, side, top, then back front, back side, back top */
+declare function __builtin_render(idx: number, colors: string[], shader: string[] | undefined): void;
+/** side is 0, 1, 2 for front (X), side (Y), top (Z) */
+declare function __builtin_render_with(idx: number, color: (x: number, y: number, z: number, side: number, back: boolean) => string, shader: string[] | undefined): void;
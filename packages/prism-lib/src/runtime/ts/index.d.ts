@@ -16,18 +16,29 @@ declare function unit(unit_f64: number): void;
 /**
  * Set the global shader colors in the X/Y/Z directions
  *
- * Use empty string to keep the default color
+ * Use empty string to keep the default color.
+ * This applies to all shapes rendered without their own shader
  */
 declare function shader(x: string, y: string, z: string): void;
 
 /**
- * Set the global scale for all object creation
+ * Set the number of grid cells per unit on each axis (default 1)
  *
- * This allows you to specify decimals when creating objects that
- * require integer values. For example, if you set the scale to 2,
- * then you can specify a point with 0.5 without error.
+ * Coordinates and lengths can be any multiple of `1 / n`. For example,
+ * at resolution 2, `point(0.5, 0, 0)` is valid and `size(0.5, 1, 1)` renders
+ * at half the width of `size(1, 1, 1)`. Descriptions of generated shapes
+ * that mention unit cubes refer to grid cells at higher resolutions
  *
- * Calling this will not affect objects that were created previously
+ * This must be called before any shape is created, and throws otherwise
+ */
+declare function resolution(n: number): void;
+
+/**
+ * Set the resolution to `n`, but render each grid cell at the unit length,
+ * so everything is scaled up by `n`
+ *
+ * @deprecated use `resolution` instead. Unlike before, `n` must be a
+ * positive integer and this must be called before any shape is created
  */
 declare function scale(n: number): void;
 /** 
@@ -45,9 +56,209 @@ declare function intersection(a: Shape | Prism, b: Shape | Prism): Shape
  * (i.e. contains point A if this contains A but the given shape does not)
  */
 declare function difference(a: Shape | Prism, b: Shape | Prism): Shape
+/** 
+ * Create a new shape that is the symmetric difference of 2 shapes
+ * (i.e. contains point A if exactly one of the shapes contains A)
+ */
+declare function xor(a: Shape | Prism, b: Shape | Prism): Shape
+/**
+ * Create a new shape that is the union of all the shapes
+ *
+ * This is faster than calling `union` repeatedly when combining many shapes.
+ * Returns an empty shape if the array is empty
+ */
+declare function unionAll(shapes: (Shape | Prism)[]): Shape
+/**
+ * Create a new shape that is the intersection of all the shapes
+ *
+ * This is faster than calling `intersection` repeatedly when combining many shapes.
+ * Returns an empty shape if the array is empty
+ */
+declare function intersectAll(shapes: (Shape | Prism)[]): Shape
 
-/** Render this shape into the scene */
-declare function render(shape: Shape, color: string): void
+/**
+ * Create a sphere made of unit cubes
+ *
+ * A unit cube is part of the sphere if its center is within the radius
+ * of the center of the unit cube at the center point
+ */
+declare function sphere(center: Point, radius: number): Shape
+/**
+ * Create an ellipsoid made of unit cubes, with the radius on each axis
+ *
+ * A unit cube is part of the ellipsoid if its center is inside
+ * the ellipsoid centered at the center of the unit cube at the center point
+ */
+declare function ellipsoid(center: Point, radii: Size | [number, number, number]): Shape
+/**
+ * Create a cylinder made of unit cubes
+ *
+ * The base is the center unit cube of the bottom disc, and the cylinder
+ * extends `height` unit cubes along the axis (default `"z"`)
+ * from there (including the base)
+ */
+declare function cylinder(base: Point, radius: number, height: number, axis?: Axis | SignedAxis): Shape
+/**
+ * Create a shape from the unit cubes in the bounding prism that pass the predicate
+ *
+ * The predicate is called natively with the position of each unit cube,
+ * so it is not affected by the loop limit. The bounding prism
+ * can contain at most 1048576 unit cubes.
+ */
+declare function voxelize(bound: Prism, predicate: (x: number, y: number, z: number) => boolean): Shape
+/**
+ * Create stairs ascending in the horizontal direction
+ *
+ * Each step is a column with the footprint of the step size,
+ * where the first (lowest) step has the min position at origin
+ * and the height of the step size. Each following step is next to
+ * the previous step in the direction, and higher by the step size's height
+ */
+declare function stairs(origin: Point, direction: Axis | SignedAxis, steps: number, stepSize: Size): Shape
+/**
+ * Create a stepped pyramid with the base prism as the bottom level
+ *
+ * Each level has the same height as the base, and is inset by 1 unit
+ * on each horizontal side from the level below. Levels are added
+ * until the number of levels is reached or there is no room
+ */
+declare function pyramid(base: Prism, levels?: number): Shape
+/**
+ * Create a wedge (ramp) inside the bounding prism, rising in the horizontal
+ * direction from the bottom to the top of the prism
+ *
+ * The slope is approximated by steps of unit cubes
+ */
+declare function wedge(bound: Prism, direction: Axis | SignedAxis): Shape
+/**
+ * Create a straight line of unit cubes from the unit cube at `from`
+ * to the unit cube at `to` (both included)
+ *
+ * The line is drawn with a cube of `thickness` units (default 1) centered
+ * on each unit cube of the line. A thin diagonal line only connects
+ * at the edges and corners of the unit cubes
+ *
+ * The number of unit cubes on the line times `thickness` squared
+ * can be at most 1048576
+ */
+declare function line(from: Point, to: Point, thickness?: number): Shape
+/**
+ * Create a path of lines connecting the points in order,
+ * see `line` for how each line is drawn
+ *
+ * If `closed` is true, the last point is also connected to the first point.
+ * The limit on the length applies to all the lines together
+ */
+declare function path(points: Point[], thickness?: number, options?: { closed?: boolean }): Shape
+/**
+ * Create terrain from a grid of heights, where each cell is a column of unit cubes
+ * with the bottom at the origin. `grid[row][col]` is the cell at X = col and Y = row
+ * from the origin, so each row of the grid goes along X
+ *
+ * The height of each cell is `base + round(grid[row][col] * scale)` (default scale 1,
+ * base 0), and cells with a height of 0 or less are empty
+ *
+ * The CLI can load a grayscale PNG as a grid with `--heightmap NAME=PATH`
+ */
+declare function heightmap(origin: Point, grid: number[][], options?: { scale?: number, base?: number }): Shape
+/**
+ * Options for creating shapes from text
+ */
+declare type TextOptions = {
+    /** Position of the min corner of the text grid (default origin) */
+    at?: Point
+    /**
+     * Characters that are part of the shape
+     * (default all characters except `.` and whitespace)
+     */
+    marks?: string
+}
+/**
+ * Create a shape from rows of text, where each marked character
+ * is a unit cube extended `depth` units (default 1) along the axis
+ *
+ * For the `"z"` axis, the text is a floor plan where characters go along X
+ * and rows go along Y. For the `"x"` and `"y"` axes, the text is upright
+ * with the first row at the top. For example:
+ * ```
+ * extrude([
+ *     "#..#",
+ *     "####",
+ * ], "z", 3)
+ * ```
+ */
+declare function extrude(rows: string[], axis: Axis, depth?: number, options?: TextOptions): Shape
+/**
+ * Create a shape from layers of text stacked on top of each other,
+ * where the first layer is the bottom
+ *
+ * Each layer is 1 unit high and laid out like a floor plan in `extrude`
+ */
+declare function layers(layers: string[][], options?: TextOptions): Shape
+/**
+ * Create a shape from a string drawn with a bitmap font, where each pixel
+ * is a unit cube extended `depth` units (default 1) along the axis
+ *
+ * The text is laid out like `extrude`, with the min corner at the origin.
+ * The default axis `"y"` makes upright text that reads from the front,
+ * and `"z"` makes text lying on the floor. Lines are split by `\n`
+ *
+ * The only bundled font is `"5x7"` (the default). Characters outside
+ * printable ASCII are drawn as `?`
+ */
+declare function text3d(origin: Point, text: string, options?: { axis?: Axis, depth?: number, font?: "5x7" }): Shape
+
+/**
+ * Render this shape into the scene
+ *
+ * The color is either one CSS color for all faces,
+ * or different colors for each direction, see `FaceColors`,
+ * or a function that returns the color of each unit face, see `FaceColorFn`
+ */
+declare function render(shape: Shape, color: string | FaceColors | FaceColorFn, options?: RenderOptions): void
+/**
+ * Function that returns the color of a unit face, for example
+ * `(x, y) => (x + y) % 2 ? "white" : "black"` for a checkerboard
+ *
+ * The function is called natively with the position of the unit cube
+ * in the shape that has the face, the direction of the face, and if it is
+ * a back face (which is only rendered if any face is translucent, see `FaceColors`).
+ * At most 1048576 faces can be colored by a function in each render
+ */
+declare type FaceColorFn = (x: number, y: number, z: number, side: "top" | "front" | "side", back: boolean) => string
+declare type RenderOptions = {
+    /**
+     * Shader colors in the X/Y/Z directions for this shape instead of the global
+     * `shader`, where empty strings are the default colors. Use `"none"` for
+     * unshaded faces, such as lights or overlays
+     */
+    shader?: [string, string, string] | "none"
+}
+/**
+ * Colors of the faces in each direction
+ *
+ * Each direction not given uses `color`. For example,
+ * `{ color: "saddlebrown", top: "green" }` is a grass-topped dirt block
+ */
+declare type FaceColors = {
+    /** Color for the directions not given */
+    color?: string
+    /** Color of the faces pointing up (+Z) */
+    top?: string
+    /** Color of the faces pointing to the front (+X) */
+    front?: string
+    /** Color of the faces pointing to the side (+Y) */
+    side?: string
+    /**
+     * Colors of the back faces, which are only visible through translucent faces.
+     * `back.top` is the bottom (-Z) face, `back.front` is the -X face,
+     * and `back.side` is the -Y face
+     *
+     * Each direction not given uses the color of the front face
+     * in the same direction
+     */
+    back?: string | Omit<FaceColors, "back">
+}
 
 /** Show the current object id for debugging */
 declare function debug(): void;
@@ -244,6 +455,107 @@ declare type ShapeOperation = {
     min(axis: Axis): number
     /** Get the max position of the bounding prism */
     max(axis: Axis): number
+    /** Check if the shape contains no unit cubes */
+    get isEmpty(): boolean
+    /** Get the number of unit cubes in the shape */
+    get volume(): number
+    /** Check if the shape contains the unit cube at the point */
+    contains(point: Point): boolean
+    /** Check if this and the given shape have any unit cube in common */
+    intersects(shape: Shape | Prism): boolean
+    /**
+     * Get prisms that make up the shape, which do not overlap
+     * with each other
+     */
+    boxes(): Prism[]
+    /**
+     * Split the shape into pieces that are connected to each other
+     *
+     * By default, unit cubes are connected if they share a face. With "edge" or "vertex",
+     * unit cubes that only share an edge or a corner are also connected.
+     * The pieces are ordered by position, and an empty shape has no pieces
+     */
+    components(connectivity?: "face" | "edge" | "vertex"): Shape[]
+    /**
+     * Create a new shape rotated by quarter turns (90 degrees each) around the axis
+     *
+     * Positive turns are counter-clockwise when looking from the positive end
+     * of the axis, negative turns are clockwise. If the pivot is given,
+     * the shape is rotated around that point. Otherwise, the shape is
+     * rotated in place (the min position of the bounding prism stays the same)
+     */
+    rotated(axis: Axis, quarterTurns: number, pivot?: Point): Shape
+    /**
+     * Create a new shape reflected across the plane perpendicular to the axis
+     * at the given position (e.g. `mirrored("x", 0)` maps the unit cube at x=0 to x=-1)
+     */
+    mirrored(axis: Axis, plane: number): Shape
+    /**
+     * Create a new shape that is the union of this shape and its reflection
+     * across the plane perpendicular to the axis at the given position
+     */
+    symmetric(axis: Axis, plane: number): Shape
+    /**
+     * Create a new shape scaled by an integer factor on all axes
+     *
+     * Every unit cube becomes a `factor`-sized cube. If the origin is given,
+     * the shape is scaled relative to that point. Otherwise, the shape is
+     * scaled in place (the min position of the bounding prism stays the same)
+     */
+    scaled(factor: number, origin?: Point): Shape
+    /**
+     * Create a new shape scaled by an integer factor on each axis
+     *
+     * If the origin is given, the shape is scaled relative to that point.
+     * Otherwise, the shape is scaled in place (the min position of the
+     * bounding prism stays the same)
+     */
+    scaled(x: number, y: number, z: number, origin?: Point): Shape
+    /**
+     * Create a new shape that only keeps the unit cubes within the thickness
+     * of the surface of this shape (e.g. the walls of a room)
+     *
+     * The sides in `open` are removed, for example `{ open: ["+z"] }`
+     * makes an open-top box
+     */
+    hollowed(thickness: number, options?: { open?: (Axis | SignedAxis)[] }): Shape
+    /**
+     * Create a new shape with the enclosed empty space inside this shape filled in,
+     * for example, turning a hollow sphere into a solid sphere
+     *
+     * Empty space is enclosed if it cannot be reached from outside
+     * the shape by moving through faces of empty unit cubes
+     */
+    filled(): Shape
+    /**
+     * Create a new shape grown by n units in both directions on the axes
+     * (all axes if not specified)
+     *
+     * For example, dilating a unit cube by 1 on all axes gives a 3x3x3 cube
+     */
+    dilated(n: number, axes?: Axis | Axis[]): Shape
+    /**
+     * Create a new shape shrunk by n units in both directions on the axes
+     * (all axes if not specified)
+     *
+     * A unit cube is kept only if all unit cubes within n units of it
+     * on the axes are in the shape
+     */
+    eroded(n: number, axes?: Axis | Axis[]): Shape
+    /**
+     * Create a new shape with `count` copies of this shape, where each copy
+     * is translated by the offset from the previous one
+     *
+     * For example, `shape.repeated(point(3, 0, 0), 4)` makes a row of 4 copies
+     * 3 units apart along the X axis
+     */
+    repeated(offset: Point, count: number): Shape
+    /**
+     * Create a new shape with `countA` x `countB` copies of this shape
+     * in a grid, where the offsets are the distances between
+     * copies in each direction of the grid
+     */
+    gridRepeated(offsetA: Point, countA: number, offsetB: Point, countB: number): Shape
     /** 
      * Create a new shape that is the union of this and the given shape
      * (i.e. contains point A if either this or the given shape contains A)
@@ -259,7 +571,12 @@ declare type ShapeOperation = {
      * (i.e. contains point A if this contains A but the given shape does not)
      */
     difference(shape: Shape | Prism): Shape
+    /** 
+     * Create a new shape that is the symmetric difference of this and the given shape
+     * (i.e. contains point A if exactly one of this and the given shape contains A)
+     */
+    xor(shape: Shape | Prism): Shape
 
-    /** Render this shape into the scene */
-    render(color: string): void
+    /** Render this shape into the scene, see the global `render` */
+    render(color: string | FaceColors | FaceColorFn, options?: RenderOptions): void
 }
@@ -22,12 +22,18 @@ const unit = __builtin_set_unit;
 const shader = __builtin_set_shader;
 const debug = __builtin_debug;
 
-var __global_scale = 1;
-function scale(factor) {
-    if (typeof factor !== 'number') {
-        throw new Error(`scale: expected number, got ${factor}`);
+function __resolution(ctx, n) {
+    if (typeof n !== 'number' || !Number.isInteger(n) || n < 1 || n > 4294967295) {
+        throw new Error(`${ctx}: expected positive integer, got ${n}`);
     }
-    __global_scale = factor;
+    return n;
+}
+function resolution(n) {
+    __builtin_set_resolution(__resolution("resolution", n), false);
+}
+function scale(factor) {
+    console.warn("scale() is deprecated, use resolution() instead");
+    __builtin_set_resolution(__resolution("scale", factor), true);
 }
 
 function __shape(ctx, a) {
@@ -40,6 +46,13 @@ function __shape(ctx, a) {
     throw new Error(`${ctx}: expected shape, got ${a}`);
 }
 
+function __shapes(ctx, shapes) {
+    if (!Array.isArray(shapes)) {
+        throw new Error(`${ctx}: expected array of shapes, got ${shapes}`);
+    }
+    return shapes.map((x) => __shape(ctx, x));
+}
+
 function union(a, b) {
     return new ShapeHandle(__builtin_shape_union(__shape("union", a), __shape("union", b)));
 }
@@ -49,38 +62,122 @@ function intersection(a, b) {
 function difference(a, b) {
     return new ShapeHandle(__builtin_shape_difference(__shape("difference", a), __shape("difference", b)));
 }
-function render(a, color) {
-    return new ShapeHandle(__builtin_render(__shape("render", a), color));
+function xor(a, b) {
+    return new ShapeHandle(__builtin_shape_xor(__shape("xor", a), __shape("xor", b)));
+}
+function unionAll(shapes) {
+    return new ShapeHandle(__builtin_shape_union_all(__shapes("unionAll", shapes)));
+}
+function intersectAll(shapes) {
+    return new ShapeHandle(__builtin_shape_intersection_all(__shapes("intersectAll", shapes)));
+}
+function render(a, color, options) {
+    return new ShapeHandle(__render("render", __shape("render", a), color, options));
 }
 
-function __int(ctx, value) {
-    if (typeof value !== 'number') {
+function __coord(ctx, value) {
+    if (typeof value !== 'number' || !Number.isFinite(value)) {
         throw new Error(`${ctx}: expected number, got ${value}`);
     }
-    value = value * __global_scale;
-    const whole = Math.round(value);
-    if (Math.abs(value - whole) > 0.00001) {
-        throw new Error(`${ctx}: expected integer, got ${value} (after scaling by ${__global_scale})`);
-    }
-    return whole;
+    return value;
 }
 
-function __into_i32(ctx, value) {
-    value = __int(ctx, value);
-    if (value < -2147483648 || value > 2147483647) {
-        throw new Error(`${ctx}: expected i32, got ${value} (after scaling by ${__global_scale}), which is out of range`);
+function __whole(ctx, value) {
+    if (typeof value !== 'number' || !Number.isInteger(value)) {
+        throw new Error(`${ctx}: expected integer, got ${value}`);
     }
     return value;
 }
 
-function __into_u32(ctx, value) {
-    value = __int(ctx, value);
+function __count(ctx, value) {
+    value = __whole(ctx, value);
     if (value < 0 || value > 4294967295) {
-        throw new Error(`${ctx}: expected u32, got ${value} (after scaling by ${__global_scale}), which is out of range`);
+        throw new Error(`${ctx}: expected non-negative integer, got ${value}`);
+    }
+    return value;
+}
+
+function __length(ctx, value) {
+    if (typeof value !== 'number' || !Number.isFinite(value) || value < 0) {
+        throw new Error(`${ctx}: expected non-negative number, got ${value}`);
     }
     return value;
 }
 
+function __render(ctx, idx, color, options) {
+    const shader = __shader(ctx, options);
+    if (typeof color !== 'function') {
+        return __builtin_render(idx, __face_colors(ctx, color), shader);
+    }
+    const sides = ["front", "side", "top"];
+    return __builtin_render_with(idx, (x, y, z, side, back) => {
+        const value = color(x, y, z, sides[side], back);
+        if (typeof value !== 'string') {
+            throw new Error(`${ctx}: expected color function to return a color string, got ${value}`);
+        }
+        return value;
+    }, shader);
+}
+
+function __face_colors(ctx, color) {
+    const front = __side_colors(ctx, color, undefined);
+    const back = typeof color === 'object' && color.back !== undefined
+        ? __side_colors(`${ctx}.back`, color.back, front)
+        : front;
+    return [...front, ...back];
+}
+
+/** Get the front, side and top colors, with defaults for each */
+function __side_colors(ctx, color, defaults) {
+    if (typeof color === 'string') {
+        return [color, color, color];
+    }
+    if (typeof color !== 'object' || color === null) {
+        throw new Error(`${ctx}: expected color string or face colors, got ${color}`);
+    }
+    return ["front", "side", "top"].map((side, i) => {
+        const value = color[side] ?? color.color ?? defaults?.[i];
+        if (typeof value !== 'string') {
+            throw new Error(`${ctx}: expected color string for ${side}, got ${value}`);
+        }
+        return value;
+    });
+}
+
+/** Get the shader colors in the render options */
+function __shader(ctx, options) {
+    const shader = options?.shader;
+    if (shader === undefined) {
+        return undefined;
+    }
+    if (shader === "none") {
+        return ["transparent", "transparent", "transparent"];
+    }
+    if (!Array.isArray(shader) || shader.length !== 3 || shader.some(c => typeof c !== 'string')) {
+        throw new Error(`${ctx}: expected shader to be "none" or 3 color strings, got ${shader}`);
+    }
+    return shader;
+}
+
+function __axis_enum(ctx, axis) {
+    switch (axis) {
+        case 'x': return 0;
+        case 'y': return 1;
+        case 'z': return 2;
+    }
+    __invalid_create_obj(`invalid axis in ${ctx}`, axis);
+}
+
+function __connectivity_enum(ctx, connectivity) {
+    switch (connectivity) {
+        case undefined:
+        case 'face': return 0;
+        case 'edge': return 1;
+        case 'vertex': return 2;
+    }
+    throw new Error(`${ctx}: expected "face", "edge" or "vertex", got ${connectivity}`);
+}
+
 function __signed_axis(axis) {
     switch (axis) {
         case 'x': return "+x";
@@ -90,6 +187,55 @@ function __signed_axis(axis) {
     return axis;
 }
 
+function __signed_axis_args(ctx, axis) {
+    switch (__signed_axis(axis)) {
+        case "+x": return [0, false];
+        case "+y": return [1, false];
+        case "+z": return [2, false];
+        case "-x": return [0, true];
+        case "-y": return [1, true];
+        case "-z": return [2, true];
+    }
+    __invalid_create_obj(`invalid axis in ${ctx}`, axis);
+}
+
+function __sides_mask(ctx, sides) {
+    if (sides === undefined) {
+        return 0;
+    }
+    if (!Array.isArray(sides)) {
+        throw new Error(`${ctx}: expected an array of sides, got ${sides}`);
+    }
+    let mask = 0;
+    for (const side of sides) {
+        const [a, negative] = __signed_axis_args(ctx, side);
+        mask |= 1 << (a * 2 + (negative ? 1 : 0));
+    }
+    return mask;
+}
+
+function __per_axis(ctx, n, axes) {
+    n = __length(ctx, n);
+    if (axes === undefined) {
+        return [n, n, n];
+    }
+    if (!Array.isArray(axes)) {
+        axes = [axes];
+    }
+    const out = [0, 0, 0];
+    for (const axis of axes) {
+        out[__axis_enum(ctx, axis)] = n;
+    }
+    return out;
+}
+
+function __point(ctx, value) {
+    if (!(value instanceof Point)) {
+        throw new Error(`${ctx}: expected point, got ${value}`);
+    }
+    return value;
+}
+
 function __invalid_create_obj(ctx, value) {
     const id = __builtin_nextid();
     throw new Error(`${ctx}: got ${value} while constructing #${id})`);
@@ -106,9 +252,9 @@ class Point {
     constructor(x, y, z) {
         const id = __builtin_nextid();
         const ctx = `constructing point #${id}`;
-        this._x = __into_i32(ctx, x);
-        this._y = __into_i32(ctx, y);
-        this._z = __into_i32(ctx, z);
+        this._x = __coord(ctx, x);
+        this._y = __coord(ctx, y);
+        this._z = __coord(ctx, z);
     }
     get x() { return this._x; }
     get y() { return this._y; }
@@ -167,9 +313,9 @@ class Size {
     constructor(x, y, z) {
         const id = __builtin_nextid();
         const ctx = `constructing size #${id}`;
-        this._x = __into_u32(ctx, x);
-        this._y = __into_u32(ctx, y);
-        this._z = __into_u32(ctx, z);
+        this._x = __length(ctx, x);
+        this._y = __length(ctx, y);
+        this._z = __length(ctx, z);
     }
     get x() { return this._x; }
     get y() { return this._y; }
@@ -260,6 +406,27 @@ class Prism {
         }
         __invalid_create_obj('invalid axis in prism.max', axis);
     }
+    get isEmpty() {
+        return this._size.x === 0 || this._size.y === 0 || this._size.z === 0;
+    }
+    get volume() {
+        return this._size.x * this._size.y * this._size.z;
+    }
+    contains(p) {
+        const q = __point("prism.contains", p);
+        return q.x >= this._point.x && q.x < this._point.x + this._size.x
+            && q.y >= this._point.y && q.y < this._point.y + this._size.y
+            && q.z >= this._point.z && q.z < this._point.z + this._size.z;
+    }
+    intersects(shape) {
+        return __builtin_shape_intersects(this._into_shape(), __shape("prism.intersects", shape));
+    }
+    boxes() {
+        return this.isEmpty ? [] : [this];
+    }
+    components(connectivity) {
+        return new ShapeHandle(this._into_shape()).components(connectivity);
+    }
     create(point, size) {
         if (point === this._point || point.x === this._point.x && point.y === this._point.y && point.z === this._point.z) {
             if (size === this.size || size.x === this._size.x && size.y === this._size.y && size.z === this._size.z) {
@@ -297,6 +464,43 @@ class Prism {
         return this.create(point, size);
     }
 
+    rotated(axis, quarterTurns, pivot) {
+        return new ShapeHandle(this._into_shape()).rotated(axis, quarterTurns, pivot);
+    }
+
+    mirrored(axis, plane) {
+        return new ShapeHandle(this._into_shape()).mirrored(axis, plane);
+    }
+    symmetric(axis, plane) {
+        return new ShapeHandle(this._into_shape()).symmetric(axis, plane);
+    }
+
+    scaled(a1, a2, a3, a4) {
+        return new ShapeHandle(this._into_shape()).scaled(a1, a2, a3, a4);
+    }
+
+    hollowed(thickness, options) {
+        return new ShapeHandle(this._into_shape()).hollowed(thickness, options);
+    }
+
+    filled() {
+        return new ShapeHandle(this._into_shape()).filled();
+    }
+
+    dilated(n, axes) {
+        return new ShapeHandle(this._into_shape()).dilated(n, axes);
+    }
+    eroded(n, axes) {
+        return new ShapeHandle(this._into_shape()).eroded(n, axes);
+    }
+
+    repeated(offset, count) {
+        return new ShapeHandle(this._into_shape()).repeated(offset, count);
+    }
+    gridRepeated(offsetA, countA, offsetB, countB) {
+        return new ShapeHandle(this._into_shape()).gridRepeated(offsetA, countA, offsetB, countB);
+    }
+
     union(shape) {
         return new ShapeHandle(__builtin_shape_union(this._into_shape(), __shape("prism.union", shape)));
     }
@@ -306,6 +510,9 @@ class Prism {
     difference(shape) {
         return new ShapeHandle(__builtin_shape_difference(this._into_shape(), __shape("prism.different", shape)));
     }
+    xor(shape) {
+        return new ShapeHandle(__builtin_shape_xor(this._into_shape(), __shape("prism.xor", shape)));
+    }
 
     _into_shape() {
         if (this._shape_cache !== undefined) {
@@ -318,9 +525,134 @@ class Prism {
         return this._shape_cache;
     }
 
-    render(color) {
-        __builtin_render(this._into_shape(), color);
+    render(color, options) {
+        __render("prism.render", this._into_shape(), color, options);
+    }
+}
+
+function sphere(center, radius) {
+    const c = __point("sphere", center);
+    const r = __length("sphere", radius);
+    return new ShapeHandle(__builtin_shape_ellipsoid(c.x, c.y, c.z, r, r, r));
+}
+function ellipsoid(center, radii) {
+    const c = __point("ellipsoid", center);
+    let rx, ry, rz;
+    if (radii instanceof Size) {
+        rx = radii.x; ry = radii.y; rz = radii.z;
+    } else if (Array.isArray(radii) && radii.length === 3) {
+        [rx, ry, rz] = radii.map((r) => __length("ellipsoid", r));
+    } else {
+        throw new Error(`ellipsoid: expected radii to be a size or [x, y, z], got ${radii}`);
+    }
+    return new ShapeHandle(__builtin_shape_ellipsoid(c.x, c.y, c.z, rx, ry, rz));
+}
+function cylinder(base, radius, height, axis) {
+    const b = __point("cylinder", base);
+    const r = __length("cylinder", radius);
+    const h = __length("cylinder", height);
+    const [a, negative] = __signed_axis_args("cylinder", axis === undefined ? "z" : axis);
+    return new ShapeHandle(__builtin_shape_cylinder(b.x, b.y, b.z, r, h, a, negative));
+}
+
+function voxelize(prism, predicate) {
+    if (!(prism instanceof Prism)) {
+        throw new Error(`voxelize: expected prism, got ${prism}`);
+    }
+    if (typeof predicate !== 'function') {
+        throw new Error(`voxelize: expected predicate to be a function, got ${predicate}`);
+    }
+    const p = prism._point;
+    const s = prism._size;
+    return new ShapeHandle(__builtin_shape_voxelize(p.x, p.y, p.z, s.x, s.y, s.z, predicate));
+}
+
+function stairs(origin, direction, steps, stepSize) {
+    const o = __point("stairs", origin);
+    const [a, negative] = __signed_axis_args("stairs", direction);
+    const n = __count("stairs", steps);
+    if (!(stepSize instanceof Size)) {
+        throw new Error(`stairs: expected step size to be a size, got ${stepSize}`);
+    }
+    return new ShapeHandle(__builtin_shape_stairs(o.x, o.y, o.z, a, negative, n, stepSize.x, stepSize.y, stepSize.z));
+}
+function pyramid(base, levels) {
+    if (!(base instanceof Prism)) {
+        throw new Error(`pyramid: expected base to be a prism, got ${base}`);
+    }
+    const n = levels === undefined ? 4294967295 : __count("pyramid", levels);
+    const p = base._point;
+    const s = base._size;
+    return new ShapeHandle(__builtin_shape_pyramid(p.x, p.y, p.z, s.x, s.y, s.z, n));
+}
+function wedge(bound, direction) {
+    if (!(bound instanceof Prism)) {
+        throw new Error(`wedge: expected bound to be a prism, got ${bound}`);
+    }
+    const [a, negative] = __signed_axis_args("wedge", direction);
+    const p = bound._point;
+    const s = bound._size;
+    return new ShapeHandle(__builtin_shape_wedge(p.x, p.y, p.z, s.x, s.y, s.z, a, negative));
+}
+
+function line(from, to, thickness) {
+    return path([from, to], thickness);
+}
+function path(points, thickness, options) {
+    if (!Array.isArray(points)) {
+        throw new Error(`path: expected an array of points, got ${points}`);
+    }
+    const coords = points.flatMap((p) => {
+        const q = __point("path", p);
+        return [q.x, q.y, q.z];
+    });
+    const t = thickness === undefined ? 1 : __length("path", thickness);
+    const closed = !!(options && options.closed);
+    return new ShapeHandle(__builtin_shape_path(coords, t, closed));
+}
+
+function heightmap(origin, grid, options) {
+    const o = __point("heightmap", origin);
+    if (!Array.isArray(grid) || !grid.every((row) => Array.isArray(row))) {
+        throw new Error(`heightmap: expected an array of rows of numbers, got ${grid}`);
+    }
+    const scale = options && options.scale !== undefined ? __length("heightmap", options.scale) : 1;
+    const base = options && options.base !== undefined ? __coord("heightmap", options.base) : 0;
+    return new ShapeHandle(__builtin_shape_heightmap(o.x, o.y, o.z, grid, scale, base));
+}
+
+function __text_rows(ctx, rows) {
+    if (!Array.isArray(rows) || !rows.every((r) => typeof r === 'string')) {
+        throw new Error(`${ctx}: expected an array of strings, got ${rows}`);
+    }
+    return rows;
+}
+function __text_options(ctx, options) {
+    const at = options && options.at !== undefined ? __point(ctx, options.at) : point(0, 0, 0);
+    const marks = options && options.marks !== undefined ? __to_string(options.marks) : undefined;
+    return [at, marks];
+}
+function extrude(rows, axis, depth, options) {
+    const r = __text_rows("extrude", rows);
+    const a = __axis_enum("extrude", axis);
+    const d = depth === undefined ? 1 : __length("extrude", depth);
+    const [at, marks] = __text_options("extrude", options);
+    return new ShapeHandle(__builtin_shape_extrude(at.x, at.y, at.z, r, a, d, marks));
+}
+function layers(layers, options) {
+    if (!Array.isArray(layers)) {
+        throw new Error(`layers: expected an array of layers, got ${layers}`);
     }
+    const l = layers.map((rows) => __text_rows("layers", rows));
+    const [at, marks] = __text_options("layers", options);
+    return new ShapeHandle(__builtin_shape_layers(at.x, at.y, at.z, l, marks));
+}
+function text3d(origin, text, options) {
+    const o = __point("text3d", origin);
+    const axis = options && options.axis !== undefined ? __axis_enum("text3d", options.axis) : 1;
+    const depth = options && options.depth !== undefined ? __length("text3d", options.depth) : 1;
+    const font = options && options.font !== undefined ? __to_string(options.font) : "5x7";
+    return new ShapeHandle(__builtin_shape_text3d(o.x, o.y, o.z, __to_string(text), axis, depth, font));
 }
 
 class ShapeHandle {
@@ -354,12 +686,36 @@ class ShapeHandle {
         }
         __invalid_create_obj('invalid axis in shape.max', axis);
     }
+    get isEmpty() {
+        return __builtin_shape_is_empty(this._idx);
+    }
+    get volume() {
+        return __builtin_shape_volume(this._idx);
+    }
+    contains(p) {
+        const q = __point("shape.contains", p);
+        return __builtin_shape_contains(this._idx, q.x, q.y, q.z);
+    }
+    intersects(shape) {
+        return __builtin_shape_intersects(this._idx, __shape("shape.intersects", shape));
+    }
+    boxes() {
+        const v = __builtin_shape_boxes(this._idx);
+        return Array.from({ length: v.length / 6 }, (_, i) => {
+            const [x, y, z, dx, dy, dz] = v.slice(i * 6, i * 6 + 6);
+            return prism(point(x, y, z), size(dx, dy, dz));
+        });
+    }
+    components(connectivity) {
+        const c = __connectivity_enum("shape.components", connectivity);
+        return __builtin_shape_components(this._idx, c).map((idx) => this.create(idx));
+    }
 
     at(a1, a2) {
         if (a2 === undefined) {
             return this.create(__builtin_shape_at_point(this._idx, a1.x, a1.y, a1.z));
         }
-        const off = __into_i32("shape.at", a2);
+        const off = __coord("shape.at", a2);
         switch (a1) {
             case 'x': return this.create(__builtin_shape_at_axis_off(this._idx, 0, off));
             case 'y': return this.create(__builtin_shape_at_axis_off(this._idx, 1, off));
@@ -373,7 +729,7 @@ class ShapeHandle {
             if (a2 === undefined) {
                 return this.create(__builtin_shape_translate(this._idx, a1.x, a1.y, a1.z));
             }
-            const off = __into_i32("shape.translated", a2);
+            const off = __coord("shape.translated", a2);
             switch (a1) {
                 case 'x': return this.create(__builtin_shape_translate_axis_off(this._idx, 0, off));
                 case 'y': return this.create(__builtin_shape_translate_axis_off(this._idx, 1, off));
@@ -384,6 +740,80 @@ class ShapeHandle {
         return this.create(__builtin_shape_translate(this._idx, a1, a2, a3));
     }
 
+    rotated(axis, quarterTurns, pivot) {
+        const a = __axis_enum("shape.rotated", axis);
+        const n = __whole("shape.rotated", quarterTurns);
+        if (pivot === undefined) {
+            return this.create(__builtin_shape_rotate(this._idx, a, n));
+        }
+        if (!(pivot instanceof Point)) {
+            throw new Error(`shape.rotated: expected pivot to be a point, got ${pivot}`);
+        }
+        return this.create(__builtin_shape_rotate_about(this._idx, a, n, pivot.x, pivot.y, pivot.z));
+    }
+
+    mirrored(axis, plane) {
+        const a = __axis_enum("shape.mirrored", axis);
+        const p = __coord("shape.mirrored", plane);
+        return this.create(__builtin_shape_mirror(this._idx, a, p));
+    }
+    symmetric(axis, plane) {
+        const a = __axis_enum("shape.symmetric", axis);
+        const p = __coord("shape.symmetric", plane);
+        return this.create(__builtin_shape_symmetric(this._idx, a, p));
+    }
+
+    scaled(a1, a2, a3, a4) {
+        let factors, origin;
+        if (typeof a2 === 'number') {
+            factors = [a1, a2, a3];
+            origin = a4;
+        } else {
+            factors = [a1, a1, a1];
+            origin = a2;
+        }
+        const [x, y, z] = factors.map((f) => __count("shape.scaled", f));
+        if (origin === undefined) {
+            return this.create(__builtin_shape_scale(this._idx, x, y, z));
+        }
+        if (!(origin instanceof Point)) {
+            throw new Error(`shape.scaled: expected origin to be a point, got ${origin}`);
+        }
+        return this.create(__builtin_shape_scale_about(this._idx, x, y, z, origin.x, origin.y, origin.z));
+    }
+
+    hollowed(thickness, options) {
+        const t = __length("shape.hollowed", thickness);
+        const open = __sides_mask("shape.hollowed", options && options.open);
+        return this.create(__builtin_shape_shell(this._idx, t, open));
+    }
+
+    filled() {
+        return this.create(__builtin_shape_filled(this._idx));
+    }
+
+    dilated(n, axes) {
+        const [x, y, z] = __per_axis("shape.dilated", n, axes);
+        return this.create(__builtin_shape_dilate(this._idx, x, y, z));
+    }
+    eroded(n, axes) {
+        const [x, y, z] = __per_axis("shape.eroded", n, axes);
+        return this.create(__builtin_shape_erode(this._idx, x, y, z));
+    }
+
+    repeated(offset, count) {
+        const o = __point("shape.repeated", offset);
+        const n = __count("shape.repeated", count);
+        return this.create(__builtin_shape_repeat(this._idx, o.x, o.y, o.z, n));
+    }
+    gridRepeated(offsetA, countA, offsetB, countB) {
+        const a = __point("shape.gridRepeated", offsetA);
+        const na = __count("shape.gridRepeated", countA);
+        const b = __point("shape.gridRepeated", offsetB);
+        const nb = __count("shape.gridRepeated", countB);
+        return this.create(__builtin_shape_repeat_grid(this._idx, a.x, a.y, a.z, na, b.x, b.y, b.z, nb));
+    }
+
     union(shape) {
         return this.create(__builtin_shape_union(this._idx, __shape("shape.union", shape)));
     }
@@ -393,8 +823,11 @@ class ShapeHandle {
     difference(shape) {
         return this.create(__builtin_shape_difference(this._idx, __shape("shape.difference", shape)));
     }
-    render(color) {
-        __builtin_render(this._idx, color);
+    xor(shape) {
+        return this.create(__builtin_shape_xor(this._idx, __shape("shape.xor", shape)));
+    }
+    render(color, options) {
+        __render("shape.render", this._idx, color, options);
     }
 
     create(idx) {
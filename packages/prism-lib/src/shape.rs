@@ -1,9 +1,12 @@
-use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, RwLockWriteGuard};
 
 use csscolorparser::Color;
 
 use crate::math::{Axis, Geom3, Vec3, nonneg};
-use crate::render::Face;
+use crate::render::{FaceColors, FaceRegion, Side};
+use crate::voxel::{self, Connectivity, RunMerger, VoxelGrid, VoxelSet};
 
 /// 3D geometry
 pub enum Shape {
@@ -82,6 +85,11 @@ impl ShapeVec {
         }
     }
 
+    /// Check if no shapes have been added
+    pub fn is_empty(&self) -> bool {
+        self.shapes.read().unwrap().len() == 1
+    }
+
     pub fn get(&self, idx: usize) -> Option<ShapeRef> {
         let size = self.shapes.read().unwrap().len();
         if idx < size {
@@ -104,6 +112,19 @@ impl ShapeVec {
         shapes.push(Shape::Arbitrary(Arbitrary::from_prism(shape)));
         idx as u32
     }
+    pub fn add_prisms(&self, prisms: Vec<Geom3>) -> u32 {
+        self.add_arbitrary(Arbitrary::from_prisms(prisms))
+    }
+    pub fn add_arbitrary(&self, shape: Arbitrary) -> u32 {
+        if shape.is_empty() {
+            // use position 0 as empty
+            return 0;
+        }
+        let mut shapes = self.shapes.write().unwrap();
+        let idx = shapes.len();
+        shapes.push(Shape::Arbitrary(shape));
+        idx as u32
+    }
 }
 
 #[derive(Clone)]
@@ -239,6 +260,115 @@ impl ShapeRef {
         }
     }
 
+    /// Rotate the shape by quarter turns around the axis
+    ///
+    /// If pivot is None, the shape is rotated in place, i.e.
+    /// the min position of the shape stays the same.
+    /// Returns None if the result is out of range
+    pub fn rotate(&self, axis: Axis, quarter_turns: u32, pivot: Option<Vec3<i32>>) -> Option<Self> {
+        let quarter_turns = quarter_turns % 4;
+        if quarter_turns == 0 {
+            return Some(self.clone());
+        }
+        self.try_map_arbitrary(|a| {
+            let pos = a.bound.pos;
+            let rotated = a.rotated(axis, quarter_turns, pivot.unwrap_or(pos))?;
+            match pivot {
+                Some(_) => Some(rotated),
+                None => {
+                    // the rotated shape must also fit when moved back
+                    Geom3::checked_new(pos, rotated.bound.size)?;
+                    Some(rotated.translated(pos - rotated.bound.pos))
+                }
+            }
+        })
+    }
+
+    /// Reflect the shape across the plane perpendicular to the axis at the given position.
+    /// Returns None if the result is out of range
+    pub fn mirror(&self, axis: Axis, plane: i32) -> Option<Self> {
+        self.try_map_arbitrary(|a| a.mirrored(axis, plane))
+    }
+
+    /// Get the union of the shape and its reflection across the plane.
+    /// Returns None if the reflection is out of range
+    pub fn symmetric(&self, axis: Axis, plane: i32) -> Option<Self> {
+        Some(self.union(&self.mirror(axis, plane)?))
+    }
+
+    /// Scale the shape by an integer factor on each axis
+    ///
+    /// If origin is None, the shape is scaled in place, i.e.
+    /// the min position of the shape stays the same.
+    /// Returns None if the result is out of range
+    pub fn scale(&self, factor: impl Into<Vec3<u32>>, origin: Option<Vec3<i32>>) -> Option<Self> {
+        let factor = factor.into();
+        if !factor.all_positive() {
+            return Some(self.new_empty());
+        }
+        if factor == (1, 1, 1).into() {
+            return Some(self.clone());
+        }
+        self.try_map_arbitrary(|a| a.scaled(factor, origin.unwrap_or(a.bound.pos)))
+    }
+
+    /// Keep only the unit cubes within thickness of the surface of the shape
+    ///
+    /// `open` are the sides (axis, negative) that are removed from the shell.
+    /// Returns None if the shape is too large
+    pub fn shell(&self, thickness: u32, open: &[(Axis, bool)]) -> Option<Self> {
+        self.try_map_arbitrary(|a| a.shell(thickness, open))
+    }
+
+    /// Fill the enclosed empty space in the shape, or None if the shape is too complex
+    pub fn filled(&self) -> Option<Self> {
+        self.try_map_arbitrary(|a| a.filled())
+    }
+
+    /// Grow the shape by the amount on each axis.
+    /// Returns None if the result is out of range
+    pub fn dilate(&self, amount: impl Into<Vec3<u32>>) -> Option<Self> {
+        let amount = amount.into();
+        if amount == (0, 0, 0).into() {
+            return Some(self.clone());
+        }
+        self.try_map_arbitrary(|a| a.dilated(amount))
+    }
+
+    /// Shrink the shape by the amount on each axis.
+    /// Returns None if the shape is too large
+    pub fn erode(&self, amount: impl Into<Vec3<u32>>) -> Option<Self> {
+        let amount = amount.into();
+        if amount == (0, 0, 0).into() {
+            return Some(self.clone());
+        }
+        self.try_map_arbitrary(|a| a.eroded(amount))
+    }
+
+    /// Repeat the shape for each (offset, count) in order, where each
+    /// repeat puts `count` copies of the previous result `offset` apart
+    ///
+    /// Returns None if there are too many copies or they are out of range
+    pub fn repeat(&self, repeats: &[(Vec3<i32>, u32)]) -> Option<Self> {
+        let copies = repeats
+            .iter()
+            .map(|(_, count)| *count as u64)
+            .try_fold(1u64, |acc, count| acc.checked_mul(count))?;
+        if copies > voxel::MAX_PARTS {
+            return None;
+        }
+        if copies == 0 {
+            return Some(self.new_empty());
+        }
+        self.try_map_arbitrary(|a| {
+            let mut shape = a.clone();
+            for (offset, count) in repeats {
+                shape = shape.repeated(*offset, *count)?;
+            }
+            Some(shape)
+        })
+    }
+
     pub fn union(&self, other: &Self) -> Self {
         if self.is_empty() {
             return other.clone();
@@ -305,20 +435,165 @@ impl ShapeRef {
         self.clone()
     }
 
-    pub fn render(&self, color: Color) -> Vec<Face> {
+    pub fn xor(&self, other: &Self) -> Self {
+        if self.is_empty() {
+            return other.clone();
+        }
+        if other.is_empty() {
+            return self.clone();
+        }
+        self.resolve_translation();
+        other.resolve_translation();
+        if let Some(mut arb) = self.read_arbitrary(|a| a.cloned()) {
+            other.read_arbitrary(|b| {
+                if let Some(b) = b {
+                    arb.xor(b);
+                }
+            });
+            return self.add(Shape::Arbitrary(arb));
+        }
+        // should be unreachable
+        self.clone()
+    }
+
+    /// Union of self and all the other shapes, as one new shape
+    pub fn union_all(&self, others: &[Self]) -> Self {
+        let shapes: Vec<_> = std::iter::once(self)
+            .chain(others)
+            .filter(|s| !s.is_empty())
+            .collect();
+        match shapes.as_slice() {
+            [] => self.new_empty(),
+            [shape] => (*shape).clone(),
+            _ => {
+                let arb = self.read_all_arbitrary(&shapes, Arbitrary::union_all);
+                self.add(Shape::Arbitrary(arb))
+            }
+        }
+    }
+
+    /// Intersection of self and all the other shapes, as one new shape
+    pub fn intersection_all(&self, others: &[Self]) -> Self {
+        let shapes: Vec<_> = std::iter::once(self).chain(others).collect();
+        if shapes.iter().any(|s| s.is_empty()) {
+            return self.new_empty();
+        }
+        if others.is_empty() {
+            return self.clone();
+        }
+        let arb = self.read_all_arbitrary(&shapes, Arbitrary::intersection_all);
+        self.add(Shape::Arbitrary(arb))
+    }
+
+    /// Check if the shape contains the unit cube at the position
+    pub fn contains(&self, pos: impl Into<Vec3<i32>>) -> bool {
+        let pos = pos.into();
+        if self.is_empty() {
+            return false;
+        }
+        self.resolve_translation();
+        self.read_arbitrary(|a| a.is_some_and(|a| a.contains(pos)))
+    }
+
+    /// Get the number of unit cubes in the shape
+    pub fn volume(&self) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        self.resolve_translation();
+        self.read_arbitrary(|a| a.map(Arbitrary::volume).unwrap_or_default())
+    }
+
+    /// Check if the shapes have any unit cube in common
+    pub fn intersects(&self, other: &Self) -> bool {
+        if self.is_empty() || other.is_empty() {
+            return false;
+        }
+        self.read_all_arbitrary(&[self, other], |shapes| match shapes {
+            [a, b] => a.intersects(b),
+            _ => false,
+        })
+    }
+
+    /// Get the disjoint prisms that make up the shape
+    pub fn boxes(&self) -> Vec<Geom3> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        self.resolve_translation();
+        self.read_arbitrary(|a| a.map(Arbitrary::boxes).unwrap_or_default())
+    }
+
+    /// Split the shape into connected components,
+    /// or None if there are too many components
+    pub fn components(&self, connectivity: Connectivity) -> Option<Vec<Self>> {
+        if self.is_empty() {
+            return Some(Vec::new());
+        }
+        self.resolve_translation();
+        let parts =
+            self.read_arbitrary(|a| a.map(|a| a.components(connectivity)).unwrap_or_default());
+        if parts.len() as u64 > voxel::MAX_PARTS {
+            return None;
+        }
+        if parts.len() == 1 {
+            return Some(vec![self.clone()]);
+        }
+        Some(
+            parts
+                .into_iter()
+                .map(|a| self.add(Shape::Arbitrary(a)))
+                .collect(),
+        )
+    }
+
+    pub fn render(&self, colors: &FaceColors) -> Vec<FaceRegion> {
         self.resolve_translation();
         self.read_arbitrary(|shape| {
             if let Some(shape) = shape {
-                let mut faces = Vec::new();
-                let color = Arc::new(color);
-                shape.render_faces(&color, &mut faces);
-                faces
+                let mut regions = Vec::new();
+                shape.render_regions(colors, &mut regions);
+                regions
             } else {
                 vec![]
             }
         })
     }
 
+    /// Render the shape with the color of each unit face from the function,
+    /// skipping faces with transparent colors
+    ///
+    /// The function is called with the unit cube in the shape that has the face,
+    /// the direction, and if it is a back face. Adjacent faces with the same
+    /// color (the same `Arc`) are merged into regions.
+    ///
+    /// If any front face is translucent, back faces are also rendered
+    pub fn render_with<E>(
+        &self,
+        mut color_at: impl FnMut(Vec3<i32>, Side, bool) -> Result<Arc<Color>, E>,
+    ) -> Result<Vec<FaceRegion>, E> {
+        self.resolve_translation();
+        let mut regions = Vec::new();
+        // the function can read shapes, so it is not called while holding the lock
+        let Some(faces) = self.read_arbitrary(|a| a.map(Arbitrary::exposed_faces)) else {
+            return Ok(regions);
+        };
+        let mut need_back_faces = false;
+        let mut back_faces = Vec::new();
+        for (dir, normal, front, back) in faces {
+            need_back_faces |=
+                push_colored_faces(dir, normal, false, front, &mut color_at, &mut regions)?;
+            back_faces.push((dir, normal, back));
+        }
+        if !need_back_faces {
+            return Ok(regions);
+        }
+        for (dir, normal, back) in back_faces {
+            push_colored_faces(dir, normal, true, back, &mut color_at, &mut regions)?;
+        }
+        Ok(regions)
+    }
+
     /// Resolve the translation of the shape
     fn resolve_translation(&self) {
         let mut guard = self.v.shapes.write().unwrap();
@@ -354,6 +629,42 @@ impl ShapeRef {
         })
     }
 
+    /// Read the shapes as arbitrary shapes with one lock, skipping empty shapes
+    fn read_all_arbitrary<T, F>(&self, shapes: &[&Self], f: F) -> T
+    where
+        F: FnOnce(&[&Arbitrary]) -> T,
+    {
+        for s in shapes {
+            s.resolve_translation();
+        }
+        let guard = self.v.shapes.read().unwrap();
+        let arbs: Vec<_> = shapes
+            .iter()
+            .filter_map(|s| match &guard[s.idx] {
+                Shape::Arbitrary(x) => Some(x),
+                _ => None,
+            })
+            .collect();
+        f(&arbs)
+    }
+
+    /// Create a new shape with an operation on self as arbitrary shape,
+    /// that might fail (returning None)
+    fn try_map_arbitrary<F>(&self, f: F) -> Option<Self>
+    where
+        F: FnOnce(&Arbitrary) -> Option<Arbitrary>,
+    {
+        if self.is_empty() {
+            return Some(self.clone());
+        }
+        self.resolve_translation();
+        match self.read_arbitrary(|a| a.map(f)) {
+            Some(Some(x)) => Some(self.add(Shape::Arbitrary(x))),
+            Some(None) => None,
+            None => Some(self.clone()),
+        }
+    }
+
     fn do_translate(&self, offset: Vec3<i32>) -> Shape {
         Shape::Translated(self.clone(), offset)
     }
@@ -378,17 +689,106 @@ impl ShapeRef {
 pub struct Arbitrary {
     prisms: Vec<Geom3>,
     bound: Geom3,
+    /// Number of prisms after the last coalesce, see [`Self::maybe_coalesce`]
+    coalesced_len: usize,
+    /// Voxels of the shape for [`Self::contains`], built on the first lookup
+    lookup: Lookup,
+}
+
+/// Lazily built voxel set of a shape, which is not kept when the shape is cloned,
+/// since clones are made to be transformed
+///
+/// The sets of all shapes share a budget of [`voxel::MAX_CACHED_CHUNKS`].
+/// A set that doesn't fit is not cached, and lookups check each prism instead
+#[derive(Debug, Default)]
+struct Lookup(OnceLock<Option<VoxelSet>>);
+
+/// Number of chunks in all the cached voxel sets
+static CACHED_CHUNKS: AtomicU64 = AtomicU64::new(0);
+
+impl Lookup {
+    /// Get the cached voxel set, building it on the first call,
+    /// or None if it can't be built or doesn't fit in the budget
+    fn get_or_build(&self, build: impl FnOnce() -> Option<VoxelSet>) -> Option<&VoxelSet> {
+        self.0
+            .get_or_init(|| {
+                let set = build()?;
+                let chunks = set.chunk_len();
+                CACHED_CHUNKS
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |cached| {
+                        cached
+                            .checked_add(chunks)
+                            .filter(|total| *total <= voxel::MAX_CACHED_CHUNKS)
+                    })
+                    .ok()?;
+                Some(set)
+            })
+            .as_ref()
+    }
+}
+
+impl Clone for Lookup {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Drop for Lookup {
+    fn drop(&mut self) {
+        if let Some(Some(set)) = self.0.get() {
+            CACHED_CHUNKS.fetch_sub(set.chunk_len(), Ordering::SeqCst);
+        }
+    }
 }
 
+/// Min number of prisms in a shape before trying to coalesce them
+const COALESCE_THRESHOLD: usize = 64;
+
+/// Min number of prism pairs in a CSG operation before it is
+/// done on voxels instead of on each pair of prisms
+const VOXEL_CSG_THRESHOLD: usize = 4096;
+
+/// Min number of prisms in a shape before [`Arbitrary::contains`]
+/// looks up the unit cube in voxels instead of checking each prism
+///
+/// Checking a prism is 6 comparisons, while a lookup hashes the chunk
+/// position, so below this the scan is about as fast as the lookup
+/// and not worth the memory of the set
+const VOXEL_LOOKUP_THRESHOLD: usize = 32;
+
 impl Arbitrary {
     /// Create shape from prism geometry
     pub fn from_prism(prism: Geom3) -> Self {
         Self {
             prisms: vec![prism],
             bound: prism,
+            coalesced_len: 1,
+            lookup: Lookup::default(),
         }
     }
 
+    /// Create shape from a list of prisms
+    pub fn from_prisms(prisms: Vec<Geom3>) -> Self {
+        let mut min = (i32::MAX, i32::MAX, i32::MAX).into();
+        let mut max = (i32::MIN, i32::MIN, i32::MIN).into();
+        let prisms: Vec<_> = prisms
+            .into_iter()
+            .filter(|p| p.has_positive_volume())
+            .collect();
+        for p in &prisms {
+            Self::update_bound(&mut min, &mut max, p);
+        }
+        let mut new = Self {
+            prisms,
+            bound: Geom3::new((0, 0, 0), (0, 0, 0)),
+            coalesced_len: 0,
+            lookup: Lookup::default(),
+        };
+        new.set_bound(min, max);
+        new.maybe_coalesce();
+        new
+    }
+
     /// Check if the shape contains no points
     pub fn is_empty(&self) -> bool {
         if self.prisms.is_empty() {
@@ -433,8 +833,144 @@ impl Arbitrary {
         new
     }
 
+    /// Create the same shape rotated by quarter turns around the axis at the pivot,
+    /// or None if the result is out of range
+    pub fn rotated(&self, axis: Axis, quarter_turns: u32, pivot: Vec3<i32>) -> Option<Self> {
+        Some(Self {
+            prisms: self
+                .prisms
+                .iter()
+                .map(|p| p.rotated(axis, quarter_turns, pivot))
+                .collect::<Option<_>>()?,
+            bound: self.bound.rotated(axis, quarter_turns, pivot)?,
+            coalesced_len: self.coalesced_len,
+            lookup: Lookup::default(),
+        })
+    }
+
+    /// Create the same shape reflected across the plane perpendicular to the axis,
+    /// or None if the result is out of range
+    pub fn mirrored(&self, axis: Axis, plane: i32) -> Option<Self> {
+        Some(Self {
+            prisms: self
+                .prisms
+                .iter()
+                .map(|p| p.mirrored(axis, plane))
+                .collect::<Option<_>>()?,
+            bound: self.bound.mirrored(axis, plane)?,
+            coalesced_len: self.coalesced_len,
+            lookup: Lookup::default(),
+        })
+    }
+
+    /// Create the same shape scaled by the factor on each axis, relative to the origin,
+    /// or None if the result is out of range
+    pub fn scaled(&self, factor: Vec3<u32>, origin: Vec3<i32>) -> Option<Self> {
+        Some(Self {
+            prisms: self
+                .prisms
+                .iter()
+                .map(|p| p.scaled(factor, origin))
+                .collect::<Option<_>>()?,
+            bound: self.bound.scaled(factor, origin)?,
+            coalesced_len: self.coalesced_len,
+            lookup: Lookup::default(),
+        })
+    }
+
+    /// Create a shell of the shape with the thickness, where the `open` sides
+    /// (axis, negative) are removed. Returns None if the shape is too large
+    pub fn shell(&self, thickness: u32, open: &[(Axis, bool)]) -> Option<Self> {
+        let mut interior = VoxelGrid::from_prisms(self.bound, &self.prisms)?;
+        let mut shell = interior.clone();
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            // open sides don't limit the interior in that direction
+            let before = if open.contains(&(axis, true)) {
+                0
+            } else {
+                thickness
+            };
+            let after = if open.contains(&(axis, false)) {
+                0
+            } else {
+                thickness
+            };
+            interior.erode(axis, before, after);
+        }
+        shell.subtract(&interior);
+        Some(Self::from_prisms(shell.into_prisms()))
+    }
+
+    /// Create the shape with the cavities filled, or None if the shape is too complex
+    ///
+    /// Cavities are empty unit cubes that are fully enclosed by the shape,
+    /// see [`voxel::cavities`]
+    pub fn filled(&self) -> Option<Self> {
+        let cavities = voxel::cavities(&self.prisms)?;
+        let mut new = self.clone();
+        if !cavities.is_empty() {
+            new.union(&Self::from_prisms(cavities));
+        }
+        Some(new)
+    }
+
+    /// Create the shape grown by the amount on each axis
+    ///
+    /// Each unit cube becomes a prism that extends the amount
+    /// in both directions. Returns None if the result is out of range
+    pub fn dilated(&self, amount: Vec3<u32>) -> Option<Self> {
+        let prisms = self
+            .prisms
+            .iter()
+            .map(|p| p.expanded(amount))
+            .collect::<Option<Vec<_>>>()?;
+        let bound = self.bound.expanded(amount)?;
+        // the expanded prisms overlap, merge them if possible
+        let shape = match VoxelGrid::from_prisms(bound, &prisms) {
+            Some(grid) => Self::from_prisms(grid.into_prisms()),
+            None => Self::from_prisms(prisms),
+        };
+        Some(shape)
+    }
+
+    /// Create the shape shrunk by the amount on each axis,
+    /// or None if the shape is too large
+    ///
+    /// A unit cube is kept if the prism extending the amount in both
+    /// directions from it is inside the shape
+    pub fn eroded(&self, amount: Vec3<u32>) -> Option<Self> {
+        let mut grid = VoxelGrid::from_prisms(self.bound, &self.prisms)?;
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            grid.erode(axis, amount.on(axis), amount.on(axis));
+        }
+        Some(Self::from_prisms(grid.into_prisms()))
+    }
+
+    /// Create the union of `count` copies of the shape, each
+    /// translated by `offset` from the previous one, or None if
+    /// the copies are out of range
+    pub fn repeated(&self, offset: Vec3<i32>, count: u32) -> Option<Self> {
+        // the last copy is the furthest one from the shape
+        let last = i32::try_from(count.saturating_sub(1)).ok()?;
+        let mut pos = self.bound.pos;
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            *pos.on_mut(axis) = offset
+                .on(axis)
+                .checked_mul(last)?
+                .checked_add(pos.on(axis))?;
+        }
+        Geom3::checked_new(pos, self.bound.size)?;
+        let mut new = self.clone();
+        for i in 1..count as i32 {
+            let o = Vec3(offset.x() * i, offset.y() * i, offset.z() * i);
+            new.union(&self.translated(o));
+        }
+        Some(new)
+    }
+
     /// Self = Self U other
     pub fn union(&mut self, other: &Self) {
+        self.lookup = Lookup::default();
         self.prisms.reserve(other.prisms.len());
         let (mut min, mut max) = if self.prisms.is_empty() {
             let min = (i32::MAX, i32::MAX, i32::MAX).into();
@@ -450,10 +986,17 @@ impl Arbitrary {
             Self::update_bound(&mut min, &mut max, p);
         }
         self.set_bound(min, max);
+        self.maybe_coalesce();
     }
 
     /// Self = Self intersection other
     pub fn intersection(&mut self, other: &Self) {
+        self.lookup = Lookup::default();
+        if let Some((mut a, b)) = self.voxels_for_csg(other) {
+            a.intersect(&b);
+            *self = Self::from_prisms(a.into_prisms());
+            return;
+        }
         // (A U B) ^ (C U D)
         // = ((A U B) ^ C) U ((A U B) ^ D)
         // = (A ^ C) U (B ^ C) U (A ^ D) U (B ^ D)
@@ -472,10 +1015,17 @@ impl Arbitrary {
             }
         }
         self.set_bound(min, max);
+        self.maybe_coalesce();
     }
 
     /// Self = Self - other
     pub fn difference(&mut self, other: &Self) {
+        self.lookup = Lookup::default();
+        if let Some((mut a, b)) = self.voxels_for_csg(other) {
+            a.subtract(&b);
+            *self = Self::from_prisms(a.into_prisms());
+            return;
+        }
         // Note the A - (B U C) is not the same as (A - B) U (A - C)
         // there might be a more efficient way to do this...
         for b in &other.prisms {
@@ -490,7 +1040,153 @@ impl Arbitrary {
                 Self::update_bound(&mut min, &mut max, p);
             }
             self.set_bound(min, max);
+            // each subtraction can split a prism into 6,
+            // so the shape needs to be kept small along the way
+            self.maybe_coalesce();
+        }
+    }
+
+    /// Check if the shape contains the unit cube at the position
+    pub fn contains(&self, pos: Vec3<i32>) -> bool {
+        if self.is_empty() || !self.bound.contains_unit_cube(pos) {
+            return false;
+        }
+        if self.prisms.len() < VOXEL_LOOKUP_THRESHOLD {
+            return self.contains_unit_cube(pos);
+        }
+        match self.lookup.get_or_build(|| self.to_voxels()) {
+            Some(voxels) => voxels.contains(pos),
+            None => self.contains_unit_cube(pos),
+        }
+    }
+
+    /// Get the number of unit cubes in the shape
+    pub fn volume(&self) -> u64 {
+        self.boxes().iter().map(Geom3::volume).sum()
+    }
+
+    /// Check if the shapes have any unit cube in common
+    pub fn intersects(&self, other: &Self) -> bool {
+        if self.is_empty() || other.is_empty() {
+            return false;
+        }
+        if !self.bound.intersection(&other.bound).has_positive_volume() {
+            return false;
+        }
+        if let Some((a, b)) = self.voxels_for_csg(other) {
+            return a.intersects(&b);
+        }
+        self.prisms.iter().any(|a| {
+            other
+                .prisms
+                .iter()
+                .any(|b| a.intersection(b).has_positive_volume())
+        })
+    }
+
+    /// Get disjoint prisms covering the same unit cubes as the shape
+    ///
+    /// The prisms of the shape can overlap after a union,
+    /// so they cannot be used directly for counting unit cubes
+    pub fn boxes(&self) -> Vec<Geom3> {
+        if let Some(prisms) = voxel::coalesce(&self.prisms) {
+            return prisms;
+        }
+        if let Some(voxels) = self.to_voxels() {
+            return voxels.into_prisms();
+        }
+        // subtract the previous prisms from each prism
+        let mut boxes: Vec<Geom3> = Vec::new();
+        for p in &self.prisms {
+            let mut parts = vec![*p];
+            for b in &boxes {
+                let mut next = Vec::new();
+                for part in &parts {
+                    part.difference(b, &mut next);
+                }
+                parts = next;
+            }
+            boxes.extend(parts);
+        }
+        boxes
+    }
+
+    /// Split the shape into connected components, see [`voxel::components`]
+    pub fn components(&self, connectivity: Connectivity) -> Vec<Self> {
+        voxel::components(&self.boxes(), connectivity)
+            .into_iter()
+            .map(Self::from_prisms)
+            .collect()
+    }
+
+    /// Self = (Self - other) U (other - Self)
+    pub fn xor(&mut self, other: &Self) {
+        if let Some((mut a, b)) = self.voxels_for_csg(other) {
+            a.xor(&b);
+            *self = Self::from_prisms(a.into_prisms());
+            return;
+        }
+        let mut other_only = other.clone();
+        other_only.difference(self);
+        self.difference(other);
+        self.union(&other_only);
+    }
+
+    /// Create the union of all the shapes, computing the bound once
+    pub fn union_all(shapes: &[&Self]) -> Self {
+        let prisms = shapes
+            .iter()
+            .flat_map(|s| s.prisms.iter().copied())
+            .collect();
+        Self::from_prisms(prisms)
+    }
+
+    /// Create the intersection of all the shapes
+    pub fn intersection_all(shapes: &[&Self]) -> Self {
+        // start with the simplest shapes to keep the intermediate results small
+        let mut shapes = shapes.to_vec();
+        shapes.sort_by_key(|s| s.prisms.len());
+        let Some((first, rest)) = shapes.split_first() else {
+            return Self::from_prisms(Vec::new());
+        };
+        let mut new = (*first).clone();
+        for s in rest {
+            if new.is_empty() {
+                break;
+            }
+            new.intersection(s);
+        }
+        new
+    }
+
+    /// Coalesce the prisms into a smaller set of disjoint prisms if there are too many
+    ///
+    /// To not waste time on shapes that cannot be merged, this is only attempted
+    /// again after the number of prisms doubles
+    fn maybe_coalesce(&mut self) {
+        if self.prisms.len() <= COALESCE_THRESHOLD.max(self.coalesced_len * 2) {
+            return;
         }
+        match voxel::coalesce(&self.prisms) {
+            Some(prisms) if prisms.len() < self.prisms.len() => self.prisms = prisms,
+            _ => {}
+        }
+        self.coalesced_len = self.prisms.len();
+    }
+
+    /// Convert the shape to a sparse voxel set,
+    /// or None if the shape is too large
+    pub fn to_voxels(&self) -> Option<VoxelSet> {
+        VoxelSet::from_prisms(&self.prisms)
+    }
+
+    /// Convert both shapes to voxels if a CSG operation between them
+    /// would be faster on voxels than on prisms
+    fn voxels_for_csg(&self, other: &Self) -> Option<(VoxelSet, VoxelSet)> {
+        if self.prisms.len().saturating_mul(other.prisms.len()) <= VOXEL_CSG_THRESHOLD {
+            return None;
+        }
+        Some((self.to_voxels()?, other.to_voxels()?))
     }
 
     fn update_bound(min: &mut Vec3<i32>, max: &mut Vec3<i32>, new_bound: &Geom3) {
@@ -530,75 +1226,310 @@ impl Arbitrary {
         false
     }
 
-    /// Render the shape with the color if the color is not transparent
+    /// Render the shape with the colors of each direction,
+    /// skipping faces with transparent colors
     ///
-    /// Only exterior faces are rendered (i.e. the shapes are welded together)
+    /// Only exterior faces are rendered (i.e. the shapes are welded together),
+    /// as regions of faces on the same plane
     ///
-    /// If the color is translucent, back faces are also rendered
-    pub fn render_faces(&self, color: &Arc<Color>, faces: &mut Vec<Face>) {
-        if color.a == 0.0 {
-            return;
+    /// If any front face is translucent, back faces are also rendered
+    pub fn render_regions(&self, colors: &FaceColors, regions: &mut Vec<FaceRegion>) {
+        let need_back_faces = colors.is_translucent();
+        for (dir, normal, front, back) in self.exposed_faces() {
+            let color = colors.get(dir, false);
+            if color.a > 0.0 {
+                regions.extend(front.iter().map(|p| FaceRegion::new(color, dir, *p)));
+            }
+            let color = colors.get(dir, true);
+            if need_back_faces && color.a > 0.0 {
+                // back faces belong to the neighbor on the negative side
+                regions.extend(back.iter().map(|p| {
+                    let cubes = Geom3::new(p.pos - normal, p.size);
+                    FaceRegion::new(color, dir, cubes).back()
+                }));
+            }
         }
-        let need_back_faces = color.a < 1.0;
-        for p in &self.prisms {
-            let x1 = p.pos.x();
-            let y1 = p.pos.y();
-            let z1 = p.pos.z();
-            // note the ends are exclusive
-            let x2 = p.x_end();
-            let y2 = p.y_end();
-            let z2 = p.z_end();
-            // top
-            for x in x1..x2 {
-                for y in y1..y2 {
-                    // x, y, z2
-                    // ___ <- face
-                    // x, y, z2 - 1 SELF
-                    if !self.contains_unit_cube((x, y, z2)) {
-                        faces.push(Face::top(color, (x, y, z2 - 1)));
+    }
+
+    /// Get the exposed faces in each direction that can be seen
+    pub fn exposed_faces(&self) -> Vec<ExposedFaces> {
+        FACE_NORMALS
+            .iter()
+            .map(|&(dir, axis, normal)| {
+                let (front, back) = self.exposed(axis, normal);
+                (dir, normal, front, back)
+            })
+            .collect()
+    }
+
+    /// Get the unit cubes without a neighbor in the direction of the normal,
+    /// and the unit cubes without a neighbor in the opposite direction
+    ///
+    /// Each of the returned prisms is 1 unit thick on the axis of the normal
+    fn exposed(&self, axis: Axis, normal: Vec3<i32>) -> (Vec<Geom3>, Vec<Geom3>) {
+        if let Some(faces) = voxel::exposed_faces(&self.prisms, axis) {
+            return faces;
+        }
+        // too fragmented to sweep, so subtract the shape moved by a unit instead
+        let exposed = |normal: Vec3<i32>| {
+            let mut exposed = self.clone();
+            exposed.difference(&self.translated(Vec3(0, 0, 0) - normal));
+            exposed.prisms
+        };
+        (exposed(normal), exposed(Vec3(0, 0, 0) - normal))
+    }
+}
+
+/// Directions of the faces that can be seen, with their axes and normal vectors
+const FACE_NORMALS: [(Side, Axis, Vec3<i32>); 3] = [
+    (Side::Top, Axis::Z, Vec3(0, 0, 1)),
+    (Side::Front, Axis::X, Vec3(1, 0, 0)),
+    (Side::Side, Axis::Y, Vec3(0, 1, 0)),
+];
+
+/// Exposed faces in a direction, as (direction, normal vector, unit cubes
+/// with front faces, unit cubes with back faces on the negative side)
+pub type ExposedFaces = (Side, Vec3<i32>, Vec<Geom3>, Vec<Geom3>);
+
+/// Prisms of unit cubes with the same color
+type ColoredPrisms = (Arc<Color>, Vec<Geom3>);
+
+/// Call the function for each unit cube in the prism and merge the cubes
+/// into prisms by color
+///
+/// Colors are compared by pointer, so the function should return
+/// the same `Arc` for the same color
+fn merge_face_colors<E>(
+    prism: Geom3,
+    mut color_at: impl FnMut(Vec3<i32>) -> Result<Arc<Color>, E>,
+) -> Result<Vec<ColoredPrisms>, E> {
+    let mut mergers: HashMap<*const Color, (Arc<Color>, RunMerger)> = HashMap::new();
+    for z in prism.pos.z()..prism.z_end() {
+        for y in prism.pos.y()..prism.y_end() {
+            // the current run of the same color (x1, color)
+            let mut run: Option<(i32, Arc<Color>)> = None;
+            for x in prism.pos.x()..prism.x_end() {
+                let color = color_at(Vec3(x, y, z))?;
+                run = match run.take() {
+                    Some((x1, run_color)) if Arc::ptr_eq(&run_color, &color) => {
+                        Some((x1, run_color))
                     }
-                    // x, y, z1 SELF
-                    // ___ <- face
-                    // x, y, z1 - 1
-                    if need_back_faces && !self.contains_unit_cube((x, y, z1 - 1)) {
-                        faces.push(Face::top(color, (x, y, z1 - 1)).back());
+                    Some((x1, run_color)) => {
+                        push_face_run(&mut mergers, run_color, y, z, x1, x);
+                        Some((x, color))
                     }
-                }
+                    None => Some((x, color)),
+                };
             }
-            // front
-            for y in y1..y2 {
-                for z in z1..z2 {
-                    // ............ x2 - 1, y, z SELF
-                    // ...... ||||||  <- face
-                    // x2, y, z
-                    if !self.contains_unit_cube((x2, y, z)) {
-                        faces.push(Face::front(color, (x2 - 1, y, z)));
-                    }
-                    // ............ x1 - 1, y, z
-                    // ...... ||||||  <- face
-                    // x1, y, z SELF
-                    if need_back_faces && !self.contains_unit_cube((x1 - 1, y, z)) {
-                        faces.push(Face::front(color, (x1 - 1, y, z)).back());
-                    }
+            if let Some((x1, run_color)) = run {
+                push_face_run(&mut mergers, run_color, y, z, x1, prism.x_end());
+            }
+        }
+    }
+    Ok(mergers
+        .into_values()
+        .map(|(color, merger)| (color, merger.finish()))
+        .collect())
+}
+
+/// Color the faces on the prisms with the function and add them as regions,
+/// returning if any of the colors is translucent
+///
+/// The prisms are of the unit cubes that have the faces, see [`Arbitrary::exposed_faces`]
+fn push_colored_faces<E>(
+    dir: Side,
+    normal: Vec3<i32>,
+    back: bool,
+    prisms: Vec<Geom3>,
+    color_at: &mut impl FnMut(Vec3<i32>, Side, bool) -> Result<Arc<Color>, E>,
+    regions: &mut Vec<FaceRegion>,
+) -> Result<bool, E> {
+    let mut translucent = false;
+    for p in prisms {
+        for (color, cubes) in merge_face_colors(p, |pos| color_at(pos, dir, back))? {
+            translucent |= color.a < 1.0;
+            if color.a <= 0.0 {
+                continue;
+            }
+            regions.extend(cubes.iter().map(|c| {
+                if back {
+                    // back faces belong to the neighbor on the negative side
+                    FaceRegion::new(&color, dir, Geom3::new(c.pos - normal, c.size)).back()
+                } else {
+                    FaceRegion::new(&color, dir, *c)
                 }
+            }));
+        }
+    }
+    Ok(translucent)
+}
+
+/// Add the run of faces with the color to the merger of the color
+fn push_face_run(
+    mergers: &mut HashMap<*const Color, (Arc<Color>, RunMerger)>,
+    color: Arc<Color>,
+    y: i32,
+    z: i32,
+    x1: i32,
+    x2: i32,
+) {
+    mergers
+        .entry(Arc::as_ptr(&color))
+        .or_insert_with(|| (color, RunMerger::new()))
+        .1
+        .push(y, z, x1, x2);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::voxel::tests::{assert_disjoint, cubes, random_prisms};
+
+    fn shape(seed: u64, count: usize) -> Arbitrary {
+        Arbitrary::from_prisms(random_prisms(seed, count, 10))
+    }
+
+    fn shape_cubes(shape: &Arbitrary) -> BTreeSet<Vec3<i32>> {
+        let boxes = shape.boxes();
+        assert_disjoint(&boxes);
+        let set = cubes(&boxes);
+        assert_eq!(set, cubes(&shape.prisms));
+        set
+    }
+
+    fn map_cubes(
+        set: &BTreeSet<Vec3<i32>>,
+        f: impl Fn(Vec3<i32>) -> Vec3<i32>,
+    ) -> BTreeSet<Vec3<i32>> {
+        set.iter().map(|c| f(*c)).collect()
+    }
+
+    #[test]
+    fn csg_matches_cubes() {
+        for seed in 0..10 {
+            let (a, b) = (shape(seed, 20), shape(seed + 100, 20));
+            let (set_a, set_b) = (shape_cubes(&a), shape_cubes(&b));
+            let mut union = a.clone();
+            union.union(&b);
+            assert_eq!(shape_cubes(&union), &set_a | &set_b);
+            let mut intersection = a.clone();
+            intersection.intersection(&b);
+            assert_eq!(shape_cubes(&intersection), &set_a & &set_b);
+            let mut difference = a.clone();
+            difference.difference(&b);
+            assert_eq!(shape_cubes(&difference), &set_a - &set_b);
+            let mut xor = a.clone();
+            xor.xor(&b);
+            assert_eq!(shape_cubes(&xor), &set_a ^ &set_b);
+            assert_eq!(a.intersects(&b), !(&set_a & &set_b).is_empty());
+        }
+    }
+
+    #[test]
+    fn queries_match_cubes() {
+        for seed in 0..10 {
+            // enough prisms to use the voxel lookup
+            let a = shape(seed, VOXEL_LOOKUP_THRESHOLD * 2);
+            let set = shape_cubes(&a);
+            assert_eq!(a.volume(), set.len() as u64);
+            for p in cubes(&[Geom3::new((-1, -1, -1), (16, 16, 16))]) {
+                assert_eq!(a.contains(p), set.contains(&p), "seed {seed} {p:?}");
             }
-            // side
-            for x in x1..x2 {
-                for z in z1..z2 {
-                    // SELF
-                    // x, y2 - 1, z ... | ... x, y2, z
-                    // .................^ face
-                    if !self.contains_unit_cube((x, y2, z)) {
-                        faces.push(Face::side(color, (x, y2 - 1, z)));
-                    }
-                    // ...................... SELF
-                    // x, y1 - 1, z ... | ... x, y1, z
-                    // .................^ face
-                    if need_back_faces && !self.contains_unit_cube((x, y1 - 1, z)) {
-                        faces.push(Face::side(color, (x, y1 - 1, z)).back());
+            let bound = a.bound;
+            for p in &set {
+                assert!(bound.contains_unit_cube(*p));
+            }
+            let parts = a.components(Connectivity::Face);
+            let total: u64 = parts.iter().map(Arbitrary::volume).sum();
+            assert_eq!(total, a.volume());
+        }
+    }
+
+    #[test]
+    fn transforms_match_cubes() {
+        for seed in 0..10 {
+            let a = shape(seed, 10);
+            let set = shape_cubes(&a);
+            let pivot = Vec3(3, -2, 5);
+            // (x, y) -> (-y, x) around the pivot, for the cube at (x, y)
+            let rotated = a.rotated(Axis::Z, 1, pivot).unwrap();
+            let expected = map_cubes(&set, |c| {
+                let d = c - pivot;
+                pivot + Vec3(-d.y() - 1, d.x(), d.z())
+            });
+            assert_eq!(shape_cubes(&rotated), expected);
+            let mirrored = a.mirrored(Axis::Y, 4).unwrap();
+            let expected = map_cubes(&set, |c| Vec3(c.x(), 8 - c.y() - 1, c.z()));
+            assert_eq!(shape_cubes(&mirrored), expected);
+            let scaled = a.scaled(Vec3(2, 1, 1), Vec3(0, 0, 0)).unwrap();
+            assert_eq!(scaled.volume(), a.volume() * 2);
+            let repeated = a.repeated(Vec3(20, 0, 0), 3).unwrap();
+            assert_eq!(repeated.volume(), a.volume() * 3);
+            assert_eq!(repeated.bound.size.x(), a.bound.size.x() + 40);
+        }
+    }
+
+    #[test]
+    fn dilate_and_erode() {
+        let cube = Arbitrary::from_prism(Geom3::new((0, 0, 0), (1, 1, 1)));
+        let dilated = cube.dilated(Vec3(1, 1, 1)).unwrap();
+        assert_eq!(dilated.volume(), 27);
+        assert_eq!(dilated.bound, Geom3::new((-1, -1, -1), (3, 3, 3)));
+        assert_eq!(
+            shape_cubes(&dilated.eroded(Vec3(1, 1, 1)).unwrap()),
+            shape_cubes(&cube)
+        );
+        for seed in 0..5 {
+            let a = shape(seed, 10);
+            let set = shape_cubes(&a);
+            let dilated = a.dilated(Vec3(1, 0, 2)).unwrap();
+            let mut expected = BTreeSet::new();
+            for c in &set {
+                for dx in -1..=1 {
+                    for dz in -2..=2 {
+                        expected.insert(*c + Vec3(dx, 0, dz));
                     }
                 }
             }
+            assert_eq!(shape_cubes(&dilated), expected);
+            let eroded = a.eroded(Vec3(1, 0, 0)).unwrap();
+            let expected: BTreeSet<_> = set
+                .iter()
+                .copied()
+                .filter(|c| {
+                    set.contains(&(*c + Vec3(1, 0, 0))) && set.contains(&(*c - Vec3(1, 0, 0)))
+                })
+                .collect();
+            assert_eq!(shape_cubes(&eroded), expected);
         }
     }
+
+    #[test]
+    fn shell_and_filled() {
+        let solid = Arbitrary::from_prism(Geom3::new((0, 0, 0), (5, 5, 5)));
+        let shell = solid.shell(1, &[]).unwrap();
+        assert_eq!(shell.volume(), 125 - 27);
+        let open = solid.shell(1, &[(Axis::Z, false)]).unwrap();
+        assert_eq!(open.volume(), 125 - 27 - 9);
+        assert_eq!(shape_cubes(&shell.filled().unwrap()), shape_cubes(&solid));
+        // an open shell has no cavity to fill
+        assert_eq!(open.filled().unwrap().volume(), open.volume());
+    }
+
+    #[test]
+    fn transforms_out_of_range() {
+        let max = i32::MAX;
+        let a = Arbitrary::from_prism(Geom3::new((0, 0, 0), (2, 1, 1)));
+        assert!(a.rotated(Axis::Z, 1, Vec3(max, max, 0)).is_none());
+        assert!(a.mirrored(Axis::X, 2_000_000_000).is_none());
+        assert!(a.scaled(Vec3(3_000_000_000, 1, 1), Vec3(0, 0, 0)).is_none());
+        assert!(a.dilated(Vec3(3_000_000_000, 0, 0)).is_none());
+        assert!(a.repeated(Vec3(1_000_000_000, 0, 0), 4).is_none());
+        assert!(a.repeated(Vec3(-1_000_000_000, 0, 0), 4).is_none());
+        assert!(a.repeated(Vec3(1_000_000_000, 0, 0), 2).is_some());
+        let far = Arbitrary::from_prism(Geom3::new((max - 2, 0, 0), (2, 1, 1)));
+        assert!(far.repeated(Vec3(1, 0, 0), 2).is_none());
+    }
 }
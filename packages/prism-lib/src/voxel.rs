@@ -0,0 +1,1798 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::math::{Axis, Geom3, Vec3};
+use crate::shape::Arbitrary;
+
+/// Max number of rows a generator can scan, to prevent the script
+/// from hanging on huge shapes
+pub const MAX_ROWS: u64 = 1 << 24;
+
+/// Max number of rows covered by the brushes of a path, counting overlaps,
+/// which is checked before the path is rasterized
+pub const MAX_PATH_ROWS: u64 = 1 << 20;
+
+/// Max number of unit cubes that can be passed to a script predicate
+pub const MAX_PREDICATE_CUBES: u64 = 1 << 20;
+
+/// Max number of parts (steps, levels, etc.) a generator can create
+pub const MAX_PARTS: u64 = 1 << 16;
+
+/// Max number of unit cubes in the bound of a dense voxel grid
+pub const MAX_GRID_CUBES: u64 = 1 << 27;
+
+/// Max number of cells in the compressed grid to find cavities
+pub const MAX_COALESCE_CELLS: u64 = 1 << 22;
+
+/// Max number of ranges scanned when coalescing prisms
+pub const MAX_COALESCE_WORK: u64 = 1 << 24;
+
+/// Max number of chunks filled when creating a sparse voxel set,
+/// which is lower on WASM since memory there is limited
+#[cfg(not(target_arch = "wasm32"))]
+pub const MAX_SET_CHUNKS: u64 = 1 << 16;
+/// Max number of chunks filled when creating a sparse voxel set,
+/// which is lower on WASM since memory there is limited
+#[cfg(target_arch = "wasm32")]
+pub const MAX_SET_CHUNKS: u64 = 1 << 13;
+
+/// Max number of chunks kept in the voxel sets cached by all shapes at once,
+/// so the caches never take more memory than one set of the max size
+pub const MAX_CACHED_CHUNKS: u64 = MAX_SET_CHUNKS;
+
+/// Size of a chunk in a [`VoxelSet`] on each axis
+const CHUNK: i32 = 16;
+
+/// Merges runs of unit cubes along the X axis into prisms
+///
+/// Runs must be pushed in (z, y) order. Runs in the same row
+/// are merged if they are adjacent, and rows are merged into
+/// rectangles (then prisms) if they have the same runs.
+#[derive(Debug, Default)]
+pub struct RunMerger {
+    /// The row (y, z) being pushed
+    row: Option<(i32, i32)>,
+    /// The runs (x1, x2) of the current row
+    runs: Vec<(i32, i32)>,
+    /// Rectangles in the current z slice that can still be extended in y,
+    /// (x1, x2) -> (y1, y2)
+    rects: BTreeMap<(i32, i32), (i32, i32)>,
+    /// Completed rectangles in the current z slice (x1, x2, y1, y2)
+    slice: Vec<(i32, i32, i32, i32)>,
+    /// Prisms that can still be extended in z, (x1, x2, y1, y2) -> (z1, z2)
+    prisms: BTreeMap<(i32, i32, i32, i32), (i32, i32)>,
+    /// Completed prisms
+    out: Vec<Geom3>,
+}
+
+impl RunMerger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the cubes from x1 (inclusive) to x2 (exclusive) on row (y, z)
+    pub fn push(&mut self, y: i32, z: i32, x1: i32, x2: i32) {
+        if x1 >= x2 {
+            return;
+        }
+        match self.row {
+            Some((row_y, row_z)) if row_y == y && row_z == z => {}
+            Some((_, row_z)) => {
+                self.end_row();
+                if row_z != z {
+                    self.end_slice();
+                }
+                self.row = Some((y, z));
+            }
+            None => self.row = Some((y, z)),
+        }
+        match self.runs.last_mut() {
+            Some(last) if last.1 == x1 => last.1 = x2,
+            _ => self.runs.push((x1, x2)),
+        }
+    }
+
+    /// Finish merging and return the prisms
+    pub fn finish(mut self) -> Vec<Geom3> {
+        self.end_row();
+        self.end_slice();
+        let prisms = std::mem::take(&mut self.prisms);
+        for (rect, z) in prisms {
+            self.close_prism(rect, z);
+        }
+        self.out
+    }
+
+    fn end_row(&mut self) {
+        let Some((y, _)) = self.row else {
+            return;
+        };
+        let mut rects = BTreeMap::new();
+        for run in std::mem::take(&mut self.runs) {
+            match self.rects.remove(&run) {
+                Some((y1, y2)) if y2 == y => {
+                    rects.insert(run, (y1, y + 1));
+                }
+                old => {
+                    // a rect with the same run that ended before the previous row
+                    if let Some((y1, y2)) = old {
+                        self.slice.push((run.0, run.1, y1, y2));
+                    }
+                    rects.insert(run, (y, y + 1));
+                }
+            }
+        }
+        // rects not extended by this row are complete
+        let old = std::mem::replace(&mut self.rects, rects);
+        for ((x1, x2), (y1, y2)) in old {
+            self.slice.push((x1, x2, y1, y2));
+        }
+    }
+
+    fn end_slice(&mut self) {
+        let Some((_, z)) = self.row else {
+            return;
+        };
+        let rects = std::mem::take(&mut self.rects);
+        for ((x1, x2), (y1, y2)) in rects {
+            self.slice.push((x1, x2, y1, y2));
+        }
+        let mut prisms = BTreeMap::new();
+        for rect in std::mem::take(&mut self.slice) {
+            match self.prisms.remove(&rect) {
+                Some((z1, z2)) if z2 == z => {
+                    prisms.insert(rect, (z1, z + 1));
+                }
+                old => {
+                    // a prism with the same rect that ended before the previous slice
+                    if let Some(z) = old {
+                        self.close_prism(rect, z);
+                    }
+                    prisms.insert(rect, (z, z + 1));
+                }
+            }
+        }
+        // prisms not extended by this slice are complete
+        let old = std::mem::replace(&mut self.prisms, prisms);
+        for (rect, z) in old {
+            self.close_prism(rect, z);
+        }
+    }
+
+    fn close_prism(&mut self, (x1, x2, y1, y2): (i32, i32, i32, i32), (z1, z2): (i32, i32)) {
+        self.out.push(Geom3::new(
+            (x1, y1, z1),
+            ((x2 - x1) as u32, (y2 - y1) as u32, (z2 - z1) as u32),
+        ));
+    }
+}
+
+/// Dense grid of unit cubes inside a bound, for operations that
+/// cannot be done on prisms directly
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    bound: Geom3,
+    bits: Vec<u64>,
+}
+
+impl VoxelGrid {
+    /// Create an empty grid, or None if the bound is too large
+    pub fn new(bound: Geom3) -> Option<Self> {
+        let volume = bound.volume();
+        if volume > MAX_GRID_CUBES {
+            return None;
+        }
+        Some(Self {
+            bound,
+            bits: vec![0; volume.div_ceil(64) as usize],
+        })
+    }
+
+    /// Create a grid with the prisms filled in
+    pub fn from_prisms(bound: Geom3, prisms: &[Geom3]) -> Option<Self> {
+        let mut grid = Self::new(bound)?;
+        for p in prisms {
+            grid.fill(p);
+        }
+        Some(grid)
+    }
+
+    #[inline]
+    fn index(&self, pos: Vec3<i32>) -> Option<usize> {
+        if !self.bound.contains_unit_cube(pos) {
+            return None;
+        }
+        let x = (pos.x() - self.bound.pos.x()) as usize;
+        let y = (pos.y() - self.bound.pos.y()) as usize;
+        let z = (pos.z() - self.bound.pos.z()) as usize;
+        let sx = self.bound.size.x() as usize;
+        let sy = self.bound.size.y() as usize;
+        Some((z * sy + y) * sx + x)
+    }
+
+    /// Check if the unit cube is in the grid. Outside of the bound is always empty
+    #[inline]
+    pub fn get(&self, pos: Vec3<i32>) -> bool {
+        match self.index(pos) {
+            Some(i) => self.bits[i / 64] & (1 << (i % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// Add or remove the unit cube. Positions outside of the bound are ignored
+    #[inline]
+    pub fn set(&mut self, pos: Vec3<i32>, value: bool) {
+        if let Some(i) = self.index(pos) {
+            if value {
+                self.bits[i / 64] |= 1 << (i % 64);
+            } else {
+                self.bits[i / 64] &= !(1 << (i % 64));
+            }
+        }
+    }
+
+    /// Add all unit cubes in the prism that are inside the bound
+    pub fn fill(&mut self, prism: &Geom3) {
+        let p = prism.intersection(&self.bound);
+        for z in p.pos.z()..p.z_end() {
+            for y in p.pos.y()..p.y_end() {
+                for x in p.pos.x()..p.x_end() {
+                    self.set(Vec3(x, y, z), true);
+                }
+            }
+        }
+    }
+
+    /// Remove all unit cubes that are in the other grid (with the same bound)
+    pub fn subtract(&mut self, other: &Self) {
+        for (a, b) in self.bits.iter_mut().zip(&other.bits) {
+            *a &= !b;
+        }
+    }
+
+    /// Erode the grid along the axis, keeping a unit cube only if
+    /// all unit cubes from `before` units before it to `after` units after it
+    /// on the axis are in the grid
+    pub fn erode(&mut self, axis: Axis, before: u32, after: u32) {
+        for (start, len) in self.lines(axis) {
+            // clamp to the line length, so large thickness does not wrap when cast
+            let before = before.min(len as u32) as i32;
+            let after = after.min(len as u32) as i32;
+            let runs = self.runs_on_line(start, axis, len);
+            self.clear_line(start, axis, len);
+            for (a, b) in runs {
+                for i in a + before..b - after {
+                    self.set(step(start, axis, i), true);
+                }
+            }
+        }
+    }
+
+    /// Convert the grid into prisms
+    pub fn into_prisms(self) -> Vec<Geom3> {
+        let mut merger = RunMerger::new();
+        for (start, len) in self.lines(Axis::X) {
+            for (a, b) in self.runs_on_line(start, Axis::X, len) {
+                merger.push(start.y(), start.z(), start.x() + a, start.x() + b);
+            }
+        }
+        merger.finish()
+    }
+
+    /// Get the positions of all unit cubes in the bound, in (z, y, x) order
+    fn cells(&self) -> impl Iterator<Item = Vec3<i32>> {
+        let bound = self.bound;
+        (bound.pos.z()..bound.z_end()).flat_map(move |z| {
+            (bound.pos.y()..bound.y_end())
+                .flat_map(move |y| (bound.pos.x()..bound.x_end()).map(move |x| Vec3(x, y, z)))
+        })
+    }
+
+    /// Get the start position and length of all lines along the axis
+    ///
+    /// For the X axis, the lines are in (z, y) order
+    fn lines(&self, axis: Axis) -> Vec<(Vec3<i32>, i32)> {
+        let bound = self.bound;
+        let (a, b) = axis.rotation_plane();
+        let len = bound.size.on(axis) as i32;
+        let mut lines = Vec::new();
+        for j in bound.pos.on(b)..bound.end(b) {
+            for i in bound.pos.on(a)..bound.end(a) {
+                let mut pos = bound.pos;
+                *pos.on_mut(a) = i;
+                *pos.on_mut(b) = j;
+                lines.push((pos, len));
+            }
+        }
+        lines
+    }
+
+    /// Get the runs (a, b) of unit cubes on the line, relative to the start
+    fn runs_on_line(&self, start: Vec3<i32>, axis: Axis, len: i32) -> Vec<(i32, i32)> {
+        let mut runs = Vec::new();
+        let mut run_start = None;
+        for i in 0..len {
+            match (self.get(step(start, axis, i)), run_start) {
+                (true, None) => run_start = Some(i),
+                (false, Some(a)) => {
+                    runs.push((a, i));
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(a) = run_start {
+            runs.push((a, len));
+        }
+        runs
+    }
+
+    fn clear_line(&mut self, start: Vec3<i32>, axis: Axis, len: i32) {
+        for i in 0..len {
+            self.set(step(start, axis, i), false);
+        }
+    }
+}
+
+/// Sparse set of unit cubes, stored as 16x16x16 chunks of bits
+///
+/// Unlike [`VoxelGrid`], only the occupied chunks take memory, so shapes
+/// with parts far apart can be stored. Operations take time by the number
+/// of chunks regardless of how many prisms the shape was made of
+#[derive(Debug, Clone, Default)]
+pub struct VoxelSet {
+    /// Chunk position (in chunks) -> rows of bits on the X axis, indexed by z * 16 + y
+    chunks: HashMap<Vec3<i32>, Box<[u16; 256]>>,
+}
+
+impl VoxelSet {
+    /// Create a set with the unit cubes in the prisms, or None
+    /// if the prisms cover too many chunks
+    pub fn from_prisms(prisms: &[Geom3]) -> Option<Self> {
+        let work = prisms.iter().map(chunk_count).sum::<u64>();
+        if work > MAX_SET_CHUNKS {
+            return None;
+        }
+        let mut set = Self::default();
+        for p in prisms {
+            for key in chunks_of(p) {
+                let rows = set.chunks.entry(key).or_insert_with(|| Box::new([0; 256]));
+                update_rows(rows, key, p, |row, mask| *row |= mask);
+            }
+        }
+        Some(set)
+    }
+
+    /// Number of chunks in the set, each taking 512 bytes
+    pub fn chunk_len(&self) -> u64 {
+        self.chunks.len() as u64
+    }
+
+    /// Check if the set contains the unit cube at the position
+    pub fn contains(&self, pos: Vec3<i32>) -> bool {
+        let key = Vec3(
+            pos.x().div_euclid(CHUNK),
+            pos.y().div_euclid(CHUNK),
+            pos.z().div_euclid(CHUNK),
+        );
+        let Some(rows) = self.chunks.get(&key) else {
+            return false;
+        };
+        let x = pos.x().rem_euclid(CHUNK);
+        let y = pos.y().rem_euclid(CHUNK);
+        let z = pos.z().rem_euclid(CHUNK);
+        rows[(z * CHUNK + y) as usize] & (1 << x) != 0
+    }
+
+    /// Keep only the unit cubes that are also in the other set
+    pub fn intersect(&mut self, other: &Self) {
+        self.chunks.retain(|key, rows| match other.chunks.get(key) {
+            Some(other_rows) => {
+                for (a, b) in rows.iter_mut().zip(other_rows.iter()) {
+                    *a &= b;
+                }
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Check if any unit cube is in both sets
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.chunks
+            .iter()
+            .any(|(key, rows)| match other.chunks.get(key) {
+                Some(other_rows) => rows.iter().zip(other_rows.iter()).any(|(a, b)| a & b != 0),
+                None => false,
+            })
+    }
+
+    /// Remove all unit cubes that are in the other set
+    pub fn subtract(&mut self, other: &Self) {
+        for (key, rows) in &mut self.chunks {
+            if let Some(other_rows) = other.chunks.get(key) {
+                for (a, b) in rows.iter_mut().zip(other_rows.iter()) {
+                    *a &= !b;
+                }
+            }
+        }
+    }
+
+    /// Keep the unit cubes that are in exactly one of the sets
+    pub fn xor(&mut self, other: &Self) {
+        for (key, other_rows) in &other.chunks {
+            let rows = self
+                .chunks
+                .entry(*key)
+                .or_insert_with(|| Box::new([0; 256]));
+            for (a, b) in rows.iter_mut().zip(other_rows.iter()) {
+                *a ^= b;
+            }
+        }
+    }
+
+    /// Convert the set into disjoint prisms
+    pub fn into_prisms(self) -> Vec<Geom3> {
+        // runs need to be merged in (z, y, x) order across chunks
+        let mut runs = Vec::new();
+        for (key, rows) in &self.chunks {
+            let (x0, y0, z0) = (key.x() * CHUNK, key.y() * CHUNK, key.z() * CHUNK);
+            for (i, row) in rows.iter().enumerate() {
+                let y = y0 + i as i32 % CHUNK;
+                let z = z0 + i as i32 / CHUNK;
+                let mut row = *row;
+                while row != 0 {
+                    let a = row.trailing_zeros();
+                    let b = a + (!(row >> a)).trailing_zeros();
+                    row &= !run_mask(a as i32, b as i32);
+                    runs.push((z, y, x0 + a as i32, x0 + b as i32));
+                }
+            }
+        }
+        runs.sort_unstable();
+        let mut merger = RunMerger::new();
+        for (z, y, x1, x2) in runs {
+            merger.push(y, z, x1, x2);
+        }
+        merger.finish()
+    }
+}
+
+/// Range of chunk positions the prism overlaps on the axis
+fn chunk_span(prism: &Geom3, axis: Axis) -> Range<i32> {
+    if !prism.has_positive_volume() {
+        return 0..0;
+    }
+    let start = prism.pos.on(axis).div_euclid(CHUNK);
+    let end = (prism.end(axis) - 1).div_euclid(CHUNK) + 1;
+    start..end
+}
+
+/// Number of chunks the prism overlaps
+fn chunk_count(prism: &Geom3) -> u64 {
+    [Axis::X, Axis::Y, Axis::Z]
+        .into_iter()
+        .map(|axis| chunk_span(prism, axis).len() as u64)
+        .product()
+}
+
+/// Positions of the chunks the prism overlaps
+fn chunks_of(prism: &Geom3) -> impl Iterator<Item = Vec3<i32>> {
+    let (xs, ys) = (chunk_span(prism, Axis::X), chunk_span(prism, Axis::Y));
+    chunk_span(prism, Axis::Z).flat_map(move |z| {
+        let xs = xs.clone();
+        ys.clone()
+            .flat_map(move |y| xs.clone().map(move |x| Vec3(x, y, z)))
+    })
+}
+
+/// Update the rows of the chunk at key that overlap the prism, with the mask
+/// of the bits in the prism on each row
+fn update_rows(rows: &mut [u16; 256], key: Vec3<i32>, prism: &Geom3, f: impl Fn(&mut u16, u16)) {
+    let origin = Vec3(key.x() * CHUNK, key.y() * CHUNK, key.z() * CHUNK);
+    let p = prism.intersection(&Geom3::new(
+        origin,
+        (CHUNK as u32, CHUNK as u32, CHUNK as u32),
+    ));
+    if !p.has_positive_volume() {
+        return;
+    }
+    let mask = run_mask(p.pos.x() - origin.x(), p.x_end() - origin.x());
+    for z in p.pos.z() - origin.z()..p.z_end() - origin.z() {
+        for y in p.pos.y() - origin.y()..p.y_end() - origin.y() {
+            f(&mut rows[(z * CHUNK + y) as usize], mask);
+        }
+    }
+}
+
+/// Bits from a (inclusive) to b (exclusive) in a chunk row
+#[inline]
+fn run_mask(a: i32, b: i32) -> u16 {
+    (((1u32 << b) - 1) & !((1u32 << a) - 1)) as u16
+}
+
+/// How unit cubes need to touch to be in the same component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Sharing a face
+    Face = 0,
+    /// Sharing a face or an edge
+    Edge = 1,
+    /// Sharing a face, an edge or a corner
+    Vertex = 2,
+}
+
+impl Connectivity {
+    pub fn from_u32(connectivity: u32) -> Option<Self> {
+        match connectivity {
+            0 => Some(Self::Face),
+            1 => Some(Self::Edge),
+            2 => Some(Self::Vertex),
+            _ => None,
+        }
+    }
+}
+
+/// Group disjoint prisms into connected components
+///
+/// The components are ordered by their min prism on X, then Y, then Z
+pub fn components(prisms: &[Geom3], connectivity: Connectivity) -> Vec<Vec<Geom3>> {
+    let mut prisms = prisms.to_vec();
+    prisms.sort_by_key(|p| (p.pos.x(), p.pos.y(), p.pos.z()));
+    // union-find of the prism indices
+    let mut parent: Vec<usize> = (0..prisms.len()).collect();
+    for (i, a) in prisms.iter().enumerate() {
+        for (j, b) in prisms.iter().enumerate().skip(i + 1) {
+            // prisms after this one start too far on X to touch a
+            if b.pos.x() > a.x_end() {
+                break;
+            }
+            if touches(a, b, connectivity) {
+                let (root_a, root_b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                parent[root_b] = root_a;
+            }
+        }
+    }
+    let mut groups = HashMap::new();
+    let mut out: Vec<Vec<Geom3>> = Vec::new();
+    for (i, p) in prisms.iter().enumerate() {
+        let root = find_root(&mut parent, i);
+        let group = *groups.entry(root).or_insert_with(|| {
+            out.push(Vec::new());
+            out.len() - 1
+        });
+        out[group].push(*p);
+    }
+    out
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Check if 2 disjoint prisms have unit cubes touching with the connectivity
+fn touches(a: &Geom3, b: &Geom3, connectivity: Connectivity) -> bool {
+    // number of axes where the prisms overlap, instead of only touching
+    let mut overlaps = 0;
+    for axis in [Axis::X, Axis::Y, Axis::Z] {
+        let start = a.pos.on(axis).max(b.pos.on(axis));
+        let end = a.end(axis).min(b.end(axis));
+        match end.cmp(&start) {
+            Ordering::Less => return false,
+            Ordering::Greater => overlaps += 1,
+            Ordering::Equal => {}
+        }
+    }
+    match connectivity {
+        Connectivity::Face => overlaps >= 2,
+        Connectivity::Edge => overlaps >= 1,
+        Connectivity::Vertex => true,
+    }
+}
+
+/// Rewrite the prisms into disjoint prisms covering the same unit cubes,
+/// merging adjacent prisms where possible
+///
+/// The prisms are swept in slabs between their boundaries on one axis,
+/// and the rectangles in each slab are swept on another axis, so the cost
+/// depends on how many slabs each prism spans instead of the sizes or the
+/// number of boundaries overall. Returns None if the prisms are too fragmented
+/// to be merged efficiently
+pub fn coalesce(prisms: &[Geom3]) -> Option<Vec<Geom3>> {
+    let prisms: Vec<_> = prisms
+        .iter()
+        .filter(|p| p.has_positive_volume())
+        .copied()
+        .collect();
+    if prisms.is_empty() {
+        return Some(Vec::new());
+    }
+    // sweep on the axes (s, v) where the prisms span the fewest slabs,
+    // and the rectangles in each slab are on the (u, v) plane
+    let bounds = Vec3(
+        boundaries(&prisms, Axis::X),
+        boundaries(&prisms, Axis::Y),
+        boundaries(&prisms, Axis::Z),
+    );
+    let (s, u, v) = [Axis::X, Axis::Y, Axis::Z]
+        .into_iter()
+        .flat_map(|s| {
+            let (a, b) = s.rotation_plane();
+            [(s, a, b), (s, b, a)]
+        })
+        .min_by_key(|&(s, _, v)| {
+            prisms
+                .iter()
+                .map(|p| slabs_spanned(p, s, &bounds) * slabs_spanned(p, v, &bounds))
+                .sum::<u64>()
+        })?;
+    let ranges: Vec<_> = prisms
+        .iter()
+        .map(|p| [s, u, v].map(|axis| (p.pos.on(axis), p.end(axis))))
+        .collect();
+    let mut work = 0;
+    let mut merger = SlabMerger::default();
+    let end = for_each_slab(
+        &ranges,
+        |r| r[0],
+        |start, active| {
+            let rects: Vec<_> = active.iter().map(|r| [r[1], r[2]]).collect();
+            merger.push(start, union_rects(&rects, &mut work)?);
+            Some(())
+        },
+    )?;
+    let prisms = merger
+        .finish(end)
+        .into_iter()
+        .map(|([(u1, u2), (v1, v2)], s1, s2)| {
+            let mut pos = Vec3(0, 0, 0);
+            let mut size = Vec3(0, 0, 0);
+            for (axis, (a, b)) in [(s, (s1, s2)), (u, (u1, u2)), (v, (v1, v2))] {
+                *pos.on_mut(axis) = a;
+                *size.on_mut(axis) = (b - a) as u32;
+            }
+            Geom3::new(pos, size)
+        })
+        .collect();
+    Some(prisms)
+}
+
+/// Get the unit cubes of the prisms without a neighbor on the axis, as
+/// (cubes with a face on the positive side, cubes with a face on the negative side)
+///
+/// The prisms are swept in slabs on the axis like in [`coalesce`], and the faces
+/// are where the cross-sections of 2 adjacent slabs differ. Each returned prism
+/// is 1 unit thick on the axis. Returns None if the prisms are too fragmented
+pub fn exposed_faces(prisms: &[Geom3], axis: Axis) -> Option<(Vec<Geom3>, Vec<Geom3>)> {
+    let (u, v) = axis.rotation_plane();
+    let ranges: Vec<_> = prisms
+        .iter()
+        .filter(|p| p.has_positive_volume())
+        .map(|p| [axis, u, v].map(|a| (p.pos.on(a), p.end(a))))
+        .collect();
+    let to_prism = |[(u1, u2), (v1, v2)]: [(i32, i32); 2], s: i32| {
+        let mut pos = Vec3(0, 0, 0);
+        let mut size = Vec3(1, 1, 1);
+        *pos.on_mut(axis) = s;
+        for (a, (start, end)) in [(u, (u1, u2)), (v, (v1, v2))] {
+            *pos.on_mut(a) = start;
+            *size.on_mut(a) = (end - start) as u32;
+        }
+        Geom3::new(pos, size)
+    };
+    let mut work = 0;
+    let mut positive = Vec::new();
+    let mut negative = Vec::new();
+    // cross-section of the last slab
+    let mut last = Vec::new();
+    let end = for_each_slab(
+        &ranges,
+        |r| r[0],
+        |start, active| {
+            let rects: Vec<_> = active.iter().map(|r| [r[1], r[2]]).collect();
+            let faces = subtract_rects(&last, &rects, &mut work)?;
+            positive.extend(faces.into_iter().map(|r| to_prism(r, start - 1)));
+            let faces = subtract_rects(&rects, &last, &mut work)?;
+            negative.extend(faces.into_iter().map(|r| to_prism(r, start)));
+            last = rects;
+            Some(())
+        },
+    )?;
+    let faces = subtract_rects(&last, &[], &mut work)?;
+    positive.extend(faces.into_iter().map(|r| to_prism(r, end - 1)));
+    Some((positive, negative))
+}
+
+/// Number of slabs between the boundaries that the prism spans on the axis
+fn slabs_spanned(prism: &Geom3, axis: Axis, bounds: &Vec3<Vec<i32>>) -> u64 {
+    let b = bounds.on_ref(axis);
+    let start = b.binary_search(&prism.pos.on(axis)).unwrap_or_default();
+    let end = b.binary_search(&prism.end(axis)).unwrap_or_default();
+    end.saturating_sub(start) as u64
+}
+
+/// Merge the rectangles `[u range, v range]` into disjoint rectangles
+/// covering the same area, by sweeping on v
+///
+/// The number of ranges scanned is added to `work`.
+/// Returns None if the work is over [`MAX_COALESCE_WORK`]
+fn union_rects(rects: &[[(i32, i32); 2]], work: &mut u64) -> Option<Vec<[(i32, i32); 2]>> {
+    let mut merger = SlabMerger::default();
+    let end = for_each_slab(
+        rects,
+        |r| r[1],
+        |start, active| {
+            *work += active.len() as u64;
+            if *work > MAX_COALESCE_WORK {
+                return None;
+            }
+            merger.push(start, merge_runs(active.iter().map(|r| r[0]).collect()));
+            Some(())
+        },
+    )?;
+    let rects = merger
+        .finish(end)
+        .into_iter()
+        .map(|(u, v1, v2)| [u, (v1, v2)])
+        .collect();
+    Some(rects)
+}
+
+/// Get the area of the rectangles `[u range, v range]` in `a` that is not in `b`
+/// as disjoint rectangles, by sweeping on v like [`union_rects`]
+fn subtract_rects(
+    a: &[[(i32, i32); 2]],
+    b: &[[(i32, i32); 2]],
+    work: &mut u64,
+) -> Option<Vec<[(i32, i32); 2]>> {
+    // the rectangles tagged with if they are subtracted
+    let rects: Vec<_> = (a.iter().map(|r| (*r, false)))
+        .chain(b.iter().map(|r| (*r, true)))
+        .collect();
+    let mut merger = SlabMerger::default();
+    let end = for_each_slab(
+        &rects,
+        |(r, _)| r[1],
+        |start, active| {
+            *work += active.len() as u64;
+            if *work > MAX_COALESCE_WORK {
+                return None;
+            }
+            let runs = |subtracted: bool| {
+                merge_runs(
+                    active
+                        .iter()
+                        .filter(|(_, s)| *s == subtracted)
+                        .map(|(r, _)| r[0])
+                        .collect(),
+                )
+            };
+            let removed = runs(true);
+            let mut out = Vec::new();
+            let mut i = 0;
+            for (mut a, b) in runs(false) {
+                while i < removed.len() && removed[i].1 <= a {
+                    i += 1;
+                }
+                for &(c, d) in removed[i..].iter().take_while(|(c, _)| *c < b) {
+                    if c > a {
+                        out.push((a, c));
+                    }
+                    a = a.max(d);
+                }
+                if a < b {
+                    out.push((a, b));
+                }
+            }
+            merger.push(start, out);
+            Some(())
+        },
+    )?;
+    let rects = merger
+        .finish(end)
+        .into_iter()
+        .map(|(u, v1, v2)| [u, (v1, v2)])
+        .collect();
+    Some(rects)
+}
+
+/// Merge overlapping or touching runs into sorted disjoint runs
+fn merge_runs(mut runs: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    runs.sort_unstable();
+    let mut merged: Vec<(i32, i32)> = Vec::new();
+    for (a, b) in runs {
+        match merged.last_mut() {
+            Some(last) if last.1 >= a => last.1 = last.1.max(b),
+            _ => merged.push((a, b)),
+        }
+    }
+    merged
+}
+
+/// Call the function with the start of each slab between the boundaries
+/// of the items' ranges, and the items covering the slab
+///
+/// Returns the end of the last slab, or None if the function returns None
+fn for_each_slab<T: Copy>(
+    items: &[T],
+    range: impl Fn(&T) -> (i32, i32),
+    mut f: impl FnMut(i32, &[T]) -> Option<()>,
+) -> Option<i32> {
+    let mut bounds: Vec<_> = items
+        .iter()
+        .flat_map(|t| {
+            let (a, b) = range(t);
+            [a, b]
+        })
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+    let mut by_start = items.to_vec();
+    by_start.sort_unstable_by_key(|t| range(t).0);
+    let mut next = 0;
+    let mut active = Vec::new();
+    for w in bounds.windows(2) {
+        active.retain(|t| range(t).1 > w[0]);
+        while next < by_start.len() && range(&by_start[next]).0 == w[0] {
+            active.push(by_start[next]);
+            next += 1;
+        }
+        f(w[0], &active)?;
+    }
+    Some(bounds.last().copied().unwrap_or_default())
+}
+
+/// Merges the same keys (runs or rectangles) in consecutive slabs of a sweep
+/// into (key, start, end) of the slabs they span
+#[derive(Debug)]
+struct SlabMerger<K> {
+    /// Keys in the last slab -> start of the first slab they are in
+    open: BTreeMap<K, i32>,
+    /// Keys that are not in the last slab
+    out: Vec<(K, i32, i32)>,
+}
+
+impl<K> Default for SlabMerger<K> {
+    fn default() -> Self {
+        Self {
+            open: BTreeMap::new(),
+            out: Vec::new(),
+        }
+    }
+}
+
+impl<K: Ord> SlabMerger<K> {
+    /// Add the keys in the slab starting at `start`, right after the last slab
+    fn push(&mut self, start: i32, keys: impl IntoIterator<Item = K>) {
+        let mut open = BTreeMap::new();
+        for key in keys {
+            let key_start = self.open.remove(&key).unwrap_or(start);
+            open.insert(key, key_start);
+        }
+        for (key, key_start) in std::mem::replace(&mut self.open, open) {
+            self.out.push((key, key_start, start));
+        }
+    }
+
+    /// Finish merging, where the last slab ends at `end`
+    fn finish(mut self, end: i32) -> Vec<(K, i32, i32)> {
+        for (key, key_start) in std::mem::take(&mut self.open) {
+            self.out.push((key, key_start, end));
+        }
+        self.out
+    }
+}
+
+/// Get the empty unit cubes inside the bound of the prisms that cannot be
+/// reached from outside of the bound, moving only through faces of empty cubes
+///
+/// Like [`coalesce`], this works on the compressed coordinates.
+/// Returns None if the prisms are too fragmented
+pub fn cavities(prisms: &[Geom3]) -> Option<Vec<Geom3>> {
+    if prisms.is_empty() {
+        return Some(Vec::new());
+    }
+    let (grid, bounds) = compressed_grid(prisms)?;
+    let bound = grid.bound;
+    let mut outside = VoxelGrid::new(bound)?;
+    let mut stack = Vec::new();
+    // the empty cells on the faces of the bound can be reached from outside
+    for pos in grid.cells() {
+        let on_face = [Axis::X, Axis::Y, Axis::Z]
+            .into_iter()
+            .any(|axis| pos.on(axis) == bound.pos.on(axis) || pos.on(axis) == bound.end(axis) - 1);
+        if on_face && !grid.get(pos) {
+            outside.set(pos, true);
+            stack.push(pos);
+        }
+    }
+    while let Some(pos) = stack.pop() {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            for n in [-1, 1] {
+                let next = step(pos, axis, n);
+                if bound.contains_unit_cube(next) && !grid.get(next) && !outside.get(next) {
+                    outside.set(next, true);
+                    stack.push(next);
+                }
+            }
+        }
+    }
+    let mut cavities = VoxelGrid::new(bound)?;
+    for pos in grid.cells() {
+        if !grid.get(pos) && !outside.get(pos) {
+            cavities.set(pos, true);
+        }
+    }
+    let prisms = cavities
+        .into_prisms()
+        .into_iter()
+        .map(|p| decompress(&p, &bounds))
+        .collect();
+    Some(prisms)
+}
+
+/// Fill the prisms into a grid of compressed coordinates, where each cell
+/// is the space between adjacent boundaries of the prisms on each axis
+///
+/// Returns the grid and the boundaries, or None if the grid would be too large
+fn compressed_grid(prisms: &[Geom3]) -> Option<(VoxelGrid, Vec3<Vec<i32>>)> {
+    let bounds = Vec3(
+        boundaries(prisms, Axis::X),
+        boundaries(prisms, Axis::Y),
+        boundaries(prisms, Axis::Z),
+    );
+    let grid_bound = Geom3::new(
+        (0, 0, 0),
+        (
+            bounds.x_ref().len() as u32 - 1,
+            bounds.y_ref().len() as u32 - 1,
+            bounds.z_ref().len() as u32 - 1,
+        ),
+    );
+    if grid_bound.volume() > MAX_COALESCE_CELLS {
+        return None;
+    }
+    let compressed: Vec<_> = prisms.iter().map(|p| compress(p, &bounds)).collect();
+    let work = compressed.iter().map(Geom3::volume).sum::<u64>();
+    if work > MAX_COALESCE_CELLS {
+        return None;
+    }
+    let grid = VoxelGrid::from_prisms(grid_bound, &compressed)?;
+    Some((grid, bounds))
+}
+
+/// Sorted unique start and end coordinates of the prisms on the axis
+fn boundaries(prisms: &[Geom3], axis: Axis) -> Vec<i32> {
+    let mut out: Vec<_> = prisms
+        .iter()
+        .flat_map(|p| [p.pos.on(axis), p.end(axis)])
+        .collect();
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+/// Convert the prism to indices in the boundaries
+fn compress(prism: &Geom3, bounds: &Vec3<Vec<i32>>) -> Geom3 {
+    let mut pos = Vec3(0, 0, 0);
+    let mut size = Vec3(0, 0, 0);
+    for axis in [Axis::X, Axis::Y, Axis::Z] {
+        let b = bounds.on_ref(axis);
+        // the boundaries contain all start and end positions
+        let start = b.binary_search(&prism.pos.on(axis)).unwrap_or_default();
+        let end = b.binary_search(&prism.end(axis)).unwrap_or_default();
+        *pos.on_mut(axis) = start as i32;
+        *size.on_mut(axis) = end.saturating_sub(start) as u32;
+    }
+    Geom3::new(pos, size)
+}
+
+/// Convert the prism from indices in the boundaries back to coordinates
+fn decompress(prism: &Geom3, bounds: &Vec3<Vec<i32>>) -> Geom3 {
+    let mut pos = Vec3(0, 0, 0);
+    let mut size = Vec3(0, 0, 0);
+    for axis in [Axis::X, Axis::Y, Axis::Z] {
+        let b = bounds.on_ref(axis);
+        let start = b[prism.pos.on(axis) as usize];
+        let end = b[prism.end(axis) as usize];
+        *pos.on_mut(axis) = start;
+        *size.on_mut(axis) = (end - start) as u32;
+    }
+    Geom3::new(pos, size)
+}
+
+/// Move the position by n units along the axis
+#[inline]
+fn step(mut pos: Vec3<i32>, axis: Axis, n: i32) -> Vec3<i32> {
+    *pos.on_mut(axis) += n;
+    pos
+}
+
+/// Create prisms from a shape where each (y, z) row has at most 1 run of cubes
+///
+/// The function returns the run (x1, x2) for the row, or None if the row is empty.
+/// Returns None if there are too many rows
+pub fn from_rows(
+    y: Range<i32>,
+    z: Range<i32>,
+    mut row: impl FnMut(i32, i32) -> Option<(i32, i32)>,
+) -> Option<Vec<Geom3>> {
+    let rows = (y.end as i64 - y.start as i64).max(0) as u64
+        * (z.end as i64 - z.start as i64).max(0) as u64;
+    if rows > MAX_ROWS {
+        return None;
+    }
+    let mut merger = RunMerger::new();
+    for z in z {
+        for y in y.clone() {
+            if let Some((x1, x2)) = row(y, z) {
+                merger.push(y, z, x1, x2);
+            }
+        }
+    }
+    Some(merger.finish())
+}
+
+/// Create prisms from unit cubes inside the bound that pass the predicate
+///
+/// The predicate is called for every unit cube in (z, y, x) order
+pub fn from_predicate<E>(
+    bound: Geom3,
+    mut predicate: impl FnMut(i32, i32, i32) -> Result<bool, E>,
+) -> Result<Vec<Geom3>, E> {
+    let mut merger = RunMerger::new();
+    for z in bound.pos.z()..bound.z_end() {
+        for y in bound.pos.y()..bound.y_end() {
+            let mut start = None;
+            for x in bound.pos.x()..bound.x_end() {
+                match (predicate(x, y, z)?, start) {
+                    (true, None) => start = Some(x),
+                    (false, Some(x1)) => {
+                        merger.push(y, z, x1, x);
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(x1) = start {
+                merger.push(y, z, x1, bound.x_end());
+            }
+        }
+    }
+    Ok(merger.finish())
+}
+
+/// Create an ellipsoid centered at the unit cube at center
+///
+/// A unit cube is included if its center is inside the ellipsoid
+pub fn ellipsoid(center: Vec3<i32>, radii: Vec3<f64>) -> Option<Vec<Geom3>> {
+    let (rx, ry, rz) = (radii.x(), radii.y(), radii.z());
+    let (cx, cy, cz) = (center.x(), center.y(), center.z());
+    // the runs on each row are within this range
+    around(cx, rx)?;
+    from_rows(around(cy, ry)?, around(cz, rz)?, |y, z| {
+        let remaining = 1.0 - norm_sq(y - cy, ry) - norm_sq(z - cz, rz);
+        if remaining < -EPSILON {
+            return None;
+        }
+        let dx = half_width(rx, remaining);
+        Some(span(cx, dx))
+    })
+}
+
+/// Create a cylinder with the center of the bottom (or top if `negative`)
+/// face at the unit cube at base, extending `height` unit cubes along the axis
+pub fn cylinder(
+    base: Vec3<i32>,
+    radius: f64,
+    height: u32,
+    axis: Axis,
+    negative: bool,
+) -> Option<Vec<Geom3>> {
+    let (cx, cy, cz) = (base.x(), base.y(), base.z());
+    let start = base.on(axis);
+    let height = i32::try_from(height).ok()?;
+    let extent = if negative {
+        start.checked_sub(height)?.checked_add(1)?..start.checked_add(1)?
+    } else {
+        start..start.checked_add(height)?
+    };
+    // the cross section of the disc on row with distance d from the center
+    let disc = |d: i32| {
+        let remaining = 1.0 - norm_sq(d, radius);
+        if remaining < -EPSILON {
+            None
+        } else {
+            Some(half_width(radius, remaining))
+        }
+    };
+    if axis != Axis::X {
+        // the runs of the disc on each row are within this range
+        around(cx, radius)?;
+    }
+    match axis {
+        Axis::X => from_rows(around(cy, radius)?, around(cz, radius)?, |y, z| {
+            let remaining = 1.0 - norm_sq(y - cy, radius) - norm_sq(z - cz, radius);
+            if remaining < -EPSILON {
+                return None;
+            }
+            Some((extent.start, extent.end))
+        }),
+        Axis::Y => from_rows(extent.clone(), around(cz, radius)?, |_, z| {
+            disc(z - cz).map(|dx| span(cx, dx))
+        }),
+        Axis::Z => from_rows(around(cy, radius)?, extent.clone(), |y, _| {
+            disc(y - cy).map(|dx| span(cx, dx))
+        }),
+    }
+}
+
+/// Tolerance for floating point errors when checking if a cube is inside
+const EPSILON: f64 = 1e-9;
+
+/// Range of unit cubes around the center that can be within the radius,
+/// or None if it is out of range of the grid
+fn around(center: i32, radius: f64) -> Option<Range<i32>> {
+    let reach = (radius + EPSILON).floor().max(0.0);
+    if reach > i32::MAX as f64 {
+        return None;
+    }
+    let reach = reach as i32;
+    Some(center.checked_sub(reach)?..center.checked_add(reach)?.checked_add(1)?)
+}
+
+/// Run of unit cubes within distance d of the center
+///
+/// d is at most the reach of the radius, so the run is within
+/// [`around`], which the caller checks first
+fn span(center: i32, d: i32) -> (i32, i32) {
+    (center - d, center + d + 1)
+}
+
+/// (d / r)^2, treating 0 radius as only containing the center
+fn norm_sq(d: i32, radius: f64) -> f64 {
+    if radius <= 0.0 {
+        if d == 0 { 0.0 } else { f64::INFINITY }
+    } else {
+        let t = d as f64 / radius;
+        t * t
+    }
+}
+
+/// Max distance in whole cubes from the center on an axis with the radius,
+/// where `remaining` is the normalized squared distance left from other axes
+fn half_width(radius: f64, remaining: f64) -> i32 {
+    if radius <= 0.0 {
+        return 0;
+    }
+    (radius * remaining.max(0.0).sqrt() + EPSILON).floor() as i32
+}
+
+/// Create stairs ascending in the direction on a horizontal axis
+///
+/// Each step has the size of `step`, where the first (lowest) step has the min
+/// position at origin, and each step is `step.z` higher than the previous one
+pub fn stairs(
+    origin: Vec3<i32>,
+    axis: Axis,
+    negative: bool,
+    steps: u32,
+    step: Vec3<u32>,
+) -> Option<Arbitrary> {
+    if steps as u64 > MAX_PARTS {
+        return None;
+    }
+    let mut shape = Arbitrary::from_prisms(Vec::new());
+    if !step.all_positive() {
+        return Some(shape);
+    }
+    let run = i32::try_from(step.on(axis)).ok()?;
+    for i in 0..steps {
+        let mut pos = origin;
+        let offset = (i as i32).checked_mul(run)?;
+        let coord = pos.on_mut(axis);
+        *coord = if negative {
+            coord.checked_sub(offset)?
+        } else {
+            coord.checked_add(offset)?
+        };
+        let mut size = step;
+        *size.z_mut() = step.z().checked_mul(i + 1)?;
+        // the max corner of the step must also be on the grid
+        pos.on(axis).checked_add(run)?;
+        pos.z().checked_add(i32::try_from(size.z()).ok()?)?;
+        shape.union(&Arbitrary::from_prism(Geom3::new(pos, size)));
+    }
+    Some(shape)
+}
+
+/// Create a stepped pyramid with the base as the bottom level
+///
+/// Each level is inset by 1 unit on each horizontal side and stacked
+/// on the previous level, until the level has no area or the number
+/// of levels is reached
+pub fn pyramid(base: Geom3, levels: u32) -> Option<Arbitrary> {
+    let levels = levels
+        .min(base.size.x().div_ceil(2))
+        .min(base.size.y().div_ceil(2));
+    if levels as u64 > MAX_PARTS {
+        return None;
+    }
+    let mut shape = Arbitrary::from_prisms(Vec::new());
+    if !base.has_positive_volume() {
+        return Some(shape);
+    }
+    let height = base.size.z();
+    for i in 0..levels {
+        let inset = i32::try_from(i).ok()?;
+        let z = i32::try_from(i.checked_mul(height)?).ok()?;
+        let pos = Vec3(
+            base.pos.x().checked_add(inset)?,
+            base.pos.y().checked_add(inset)?,
+            base.pos.z().checked_add(z)?,
+        );
+        let size = Vec3(base.size.x() - 2 * i, base.size.y() - 2 * i, height);
+        // the top of the level must also be on the grid
+        let level = Geom3::checked_new(pos, size)?;
+        shape.union(&Arbitrary::from_prism(level));
+    }
+    Some(shape)
+}
+
+/// Create a wedge (ramp) inside the bound, rising in the direction
+/// on a horizontal axis from the bottom to the full height of the bound
+///
+/// The slope is approximated with steps of unit cubes
+pub fn wedge(bound: Geom3, axis: Axis, negative: bool) -> Option<Arbitrary> {
+    let length = bound.size.on(axis);
+    if length as u64 > MAX_PARTS {
+        return None;
+    }
+    let mut shape = Arbitrary::from_prisms(Vec::new());
+    if !bound.has_positive_volume() {
+        return Some(shape);
+    }
+    let height = bound.size.z() as u64;
+    // height of the column at i-th unit from the low end
+    let column = |i: u32| ((i as u64 + 1) * height).div_ceil(length as u64) as u32;
+    let mut start = 0;
+    while start < length {
+        // merge columns with the same height
+        let h = column(start);
+        let mut end = start + 1;
+        while end < length && column(end) == h {
+            end += 1;
+        }
+        let offset = if negative { length - end } else { start };
+        let mut pos = bound.pos;
+        *pos.on_mut(axis) = pos.on(axis).checked_add(i32::try_from(offset).ok()?)?;
+        let mut size = bound.size;
+        *size.on_mut(axis) = end - start;
+        *size.z_mut() = h;
+        let part = Geom3::checked_new(pos, size)?;
+        shape.union(&Arbitrary::from_prism(part));
+        start = end;
+    }
+    Some(shape)
+}
+
+/// Create a path through the points, drawn with a cube brush of `thickness` units
+///
+/// Each segment is rasterized by stepping 1 unit at a time on the axis with the
+/// largest distance, and rounding the other axes. If `closed`, the last point
+/// is also connected to the first point. Returns None if the path is too long
+/// or a brush is out of range
+pub fn path(points: &[Vec3<i32>], closed: bool, thickness: u32) -> Option<Vec<Geom3>> {
+    if thickness == 0 || points.is_empty() {
+        return Some(Vec::new());
+    }
+    let mut segments: Vec<_> = points.windows(2).map(|w| (w[0], w[1])).collect();
+    if closed && points.len() > 2 {
+        segments.push((points[points.len() - 1], points[0]));
+    }
+    let steps = segments
+        .iter()
+        .map(|(a, b)| line_steps(*a, *b))
+        .sum::<u64>();
+    // each cube on the path has a brush of thickness^2 rows
+    let rows = (steps + 1).saturating_mul(thickness as u64 * thickness as u64);
+    if rows > MAX_PATH_ROWS {
+        return None;
+    }
+    let mut cubes = vec![points[0]];
+    for (a, b) in segments {
+        let n = line_steps(a, b) as i64;
+        // the start of each segment is the end of the previous one
+        for i in 1..=n {
+            let mut pos = a;
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                let d = b.on(axis) as i64 - a.on(axis) as i64;
+                // round to nearest, with ties going up
+                *pos.on_mut(axis) = (a.on(axis) as i64 + (2 * d * i + n).div_euclid(2 * n)) as i32;
+            }
+            cubes.push(pos);
+        }
+    }
+    // center the brush on the cube, rounding down if the thickness is even
+    let half = ((thickness - 1) / 2) as i32;
+    let brushes = cubes
+        .into_iter()
+        .map(|p| {
+            let pos = Vec3(
+                p.x().checked_sub(half)?,
+                p.y().checked_sub(half)?,
+                p.z().checked_sub(half)?,
+            );
+            Geom3::checked_new(pos, Vec3(thickness, thickness, thickness))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    union_rows(&brushes)
+}
+
+/// Number of steps to rasterize a line from a to b
+fn line_steps(a: Vec3<i32>, b: Vec3<i32>) -> u64 {
+    [Axis::X, Axis::Y, Axis::Z]
+        .into_iter()
+        .map(|axis| (b.on(axis) as i64 - a.on(axis) as i64).unsigned_abs())
+        .max()
+        .unwrap_or_default()
+}
+
+/// Create disjoint prisms covering the union of the prisms
+/// by merging the runs on each row. Returns None if there are too many rows
+pub fn union_rows(prisms: &[Geom3]) -> Option<Vec<Geom3>> {
+    let rows = prisms
+        .iter()
+        .filter(|p| p.has_positive_volume())
+        .map(|p| p.size.y() as u64 * p.size.z() as u64)
+        .sum::<u64>();
+    if rows > MAX_ROWS {
+        return None;
+    }
+    let mut runs = Vec::with_capacity(rows as usize);
+    for p in prisms.iter().filter(|p| p.has_positive_volume()) {
+        for z in p.pos.z()..p.z_end() {
+            for y in p.pos.y()..p.y_end() {
+                runs.push((z, y, p.pos.x(), p.x_end()));
+            }
+        }
+    }
+    runs.sort_unstable();
+    let mut merger = RunMerger::new();
+    let mut current: Option<(i32, i32, i32, i32)> = None;
+    for (z, y, x1, x2) in runs {
+        match &mut current {
+            Some((cz, cy, _, cx2)) if *cz == z && *cy == y && x1 <= *cx2 => {
+                *cx2 = (*cx2).max(x2);
+            }
+            _ => {
+                if let Some((cz, cy, cx1, cx2)) = current.replace((z, y, x1, x2)) {
+                    merger.push(cy, cz, cx1, cx2);
+                }
+            }
+        }
+    }
+    if let Some((z, y, x1, x2)) = current {
+        merger.push(y, z, x1, x2);
+    }
+    Some(merger.finish())
+}
+
+/// Create columns of unit cubes from the origin up to the height of each cell,
+/// where `grid[row][col]` is the cell at X = col and Y = row from the origin
+///
+/// Cells in a row with the same height are merged, and so are the same
+/// runs of cells in consecutive rows. The columns are disjoint, so they are
+/// returned as is. Returns None if the shape is too large
+pub fn heightmap(origin: Vec3<i32>, grid: &[Vec<u32>]) -> Option<Vec<Geom3>> {
+    let mut prisms: Vec<Geom3> = Vec::new();
+    // runs of the previous row, (start, end, height) -> index in prisms
+    let mut open: BTreeMap<(usize, usize, u32), usize> = BTreeMap::new();
+    for (row, heights) in grid.iter().enumerate() {
+        let y = origin.y().checked_add(i32::try_from(row).ok()?)?;
+        // the row is also the end of the columns continuing from the previous row
+        y.checked_add(1)?;
+        let mut next_open = BTreeMap::new();
+        let mut start = 0;
+        while start < heights.len() {
+            let h = heights[start];
+            let mut end = start + 1;
+            while end < heights.len() && heights[end] == h {
+                end += 1;
+            }
+            if h > 0 {
+                let run = (start, end, h);
+                if let Some(i) = open.remove(&run) {
+                    *prisms[i].size.y_mut() += 1;
+                    next_open.insert(run, i);
+                } else {
+                    let x = origin.x().checked_add(i32::try_from(start).ok()?)?;
+                    let len = u32::try_from(end - start).ok()?;
+                    let column = Geom3::checked_new(Vec3(x, y, origin.z()), Vec3(len, 1, h))?;
+                    next_open.insert(run, prisms.len());
+                    prisms.push(column);
+                }
+            }
+            start = end;
+        }
+        open = next_open;
+    }
+    Some(prisms)
+}
+
+/// Create prisms from the marked characters in the rows of text, extended
+/// `depth` units from the origin in the positive direction of the axis
+///
+/// The origin is the min corner of the text grid. Columns go along
+/// X (or Y if the axis is X). Rows go along Y if the axis is Z, otherwise
+/// the first row is at the top so the text reads upright from the side.
+/// If `marks` is None, all characters except `.` and whitespace are marked.
+/// Returns None if the shape is too large
+pub fn extrude(
+    rows: &[String],
+    marks: Option<&str>,
+    origin: Vec3<i32>,
+    axis: Axis,
+    depth: u32,
+) -> Option<Vec<Geom3>> {
+    let top = i32::try_from(rows.len()).ok()? - 1;
+    let prisms = text_runs(rows, marks)
+        .map(|(row, a, b)| {
+            let len = (b - a) as u32;
+            let (offset, size) = match axis {
+                Axis::X => (Vec3(0, a, top - row), Vec3(depth, len, 1)),
+                Axis::Y => (Vec3(a, 0, top - row), Vec3(len, depth, 1)),
+                Axis::Z => (Vec3(a, row, 0), Vec3(len, 1, depth)),
+            };
+            Geom3::checked_new(origin.checked_add(offset)?, size)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    union_rows(&prisms)
+}
+
+/// Create prisms from layers of text stacked on top of each other, with
+/// the first layer at the bottom
+///
+/// Each layer is 1 unit high and laid out like [`extrude`] on the Z axis.
+/// Returns None if the shape is too large
+pub fn text_layers(
+    layers: &[Vec<String>],
+    marks: Option<&str>,
+    origin: Vec3<i32>,
+) -> Option<Vec<Geom3>> {
+    // the layer index is the z offset
+    i32::try_from(layers.len()).ok()?;
+    let prisms = layers
+        .iter()
+        .enumerate()
+        .flat_map(|(z, rows)| {
+            text_runs(rows, marks).map(move |(row, a, b)| {
+                let pos = origin.checked_add(Vec3(a, row, z as i32))?;
+                Geom3::checked_new(pos, Vec3((b - a) as u32, 1, 1))
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    union_rows(&prisms)
+}
+
+/// Get the runs (row, start, end) of marked characters in the rows,
+/// where start and end are character indices
+fn text_runs<'a>(
+    rows: &'a [String],
+    marks: Option<&'a str>,
+) -> impl Iterator<Item = (i32, i32, i32)> + 'a {
+    let is_marked = move |c: char| match marks {
+        Some(marks) => marks.contains(c),
+        None => c != '.' && !c.is_whitespace(),
+    };
+    rows.iter().enumerate().flat_map(move |(row, text)| {
+        let mut runs = Vec::new();
+        let mut start = None;
+        let mut len = 0;
+        for (i, c) in text.chars().enumerate() {
+            match (is_marked(c), start) {
+                (true, None) => start = Some(i as i32),
+                (false, Some(a)) => {
+                    runs.push((row as i32, a, i as i32));
+                    start = None;
+                }
+                _ => {}
+            }
+            len = i as i32 + 1;
+        }
+        if let Some(a) = start {
+            runs.push((row as i32, a, len));
+        }
+        runs
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use super::*;
+
+    /// Unit cubes in the prisms, as the naive reference for the algorithms
+    pub(crate) fn cubes(prisms: &[Geom3]) -> BTreeSet<Vec3<i32>> {
+        let mut out = BTreeSet::new();
+        for p in prisms {
+            for z in p.pos.z()..p.z_end() {
+                for y in p.pos.y()..p.y_end() {
+                    for x in p.pos.x()..p.x_end() {
+                        out.insert(Vec3(x, y, z));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Check that no 2 prisms share a unit cube
+    pub(crate) fn assert_disjoint(prisms: &[Geom3]) {
+        let volume: u64 = prisms.iter().map(Geom3::volume).sum();
+        assert_eq!(volume, cubes(prisms).len() as u64, "prisms overlap");
+    }
+
+    /// Prisms with random positions and sizes in a small space,
+    /// from a fixed seed so the tests are reproducible
+    pub(crate) fn random_prisms(seed: u64, count: usize, extent: u32) -> Vec<Geom3> {
+        let mut state = seed.wrapping_mul(0x9E3779B97F4A7C15) | 1;
+        let mut next = |max: u32| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % max as u64) as u32
+        };
+        (0..count)
+            .map(|_| {
+                let pos = Vec3(
+                    next(extent) as i32,
+                    next(extent) as i32,
+                    next(extent) as i32,
+                );
+                let size = Vec3(next(4) + 1, next(4) + 1, next(4) + 1);
+                Geom3::new(pos, size)
+            })
+            .collect()
+    }
+
+    fn neighbors(pos: Vec3<i32>, connectivity: Connectivity) -> Vec<Vec3<i32>> {
+        let mut out = Vec::new();
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let moved = [dx, dy, dz].iter().filter(|d| **d != 0).count();
+                    let max = match connectivity {
+                        Connectivity::Face => 1,
+                        Connectivity::Edge => 2,
+                        Connectivity::Vertex => 3,
+                    };
+                    if moved > 0 && moved <= max {
+                        out.push(pos + Vec3(dx, dy, dz));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn coalesce_matches_cubes() {
+        for seed in 0..20 {
+            let prisms = random_prisms(seed, 30, 12);
+            let merged = coalesce(&prisms).unwrap();
+            assert_disjoint(&merged);
+            assert_eq!(cubes(&merged), cubes(&prisms), "seed {seed}");
+            assert!(merged.len() <= cubes(&prisms).len());
+        }
+    }
+
+    #[test]
+    fn coalesce_merges_adjacent_prisms() {
+        let prisms = [
+            Geom3::new((0, 0, 0), (2, 3, 4)),
+            Geom3::new((2, 0, 0), (3, 3, 4)),
+        ];
+        assert_eq!(
+            coalesce(&prisms).unwrap(),
+            vec![Geom3::new((0, 0, 0), (5, 3, 4))]
+        );
+    }
+
+    #[test]
+    fn union_rows_matches_cubes() {
+        for seed in 0..10 {
+            let prisms = random_prisms(seed, 20, 10);
+            let merged = union_rows(&prisms).unwrap();
+            assert_disjoint(&merged);
+            assert_eq!(cubes(&merged), cubes(&prisms), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn exposed_faces_match_neighbors() {
+        for seed in 0..20 {
+            let prisms = random_prisms(seed, 25, 10);
+            let set = cubes(&prisms);
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                let (positive, negative) = exposed_faces(&prisms, axis).unwrap();
+                let expected = |n: i32| {
+                    set.iter()
+                        .copied()
+                        .filter(|c| !set.contains(&step(*c, axis, n)))
+                        .collect::<BTreeSet<_>>()
+                };
+                assert_disjoint(&positive);
+                assert_disjoint(&negative);
+                assert_eq!(cubes(&positive), expected(1), "seed {seed} {axis:?}");
+                assert_eq!(cubes(&negative), expected(-1), "seed {seed} {axis:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn exposed_faces_of_box() {
+        let prisms = [Geom3::new((0, 0, 0), (2, 3, 4))];
+        let (positive, negative) = exposed_faces(&prisms, Axis::Z).unwrap();
+        assert_eq!(positive, vec![Geom3::new((0, 0, 3), (2, 3, 1))]);
+        assert_eq!(negative, vec![Geom3::new((0, 0, 0), (2, 3, 1))]);
+    }
+
+    #[test]
+    fn cavities_match_flood_fill() {
+        for seed in 0..20 {
+            let prisms = random_prisms(seed, 40, 8);
+            let set = cubes(&prisms);
+            let bound = Arbitrary::from_prisms(prisms.clone());
+            let (min, max) = (
+                bound.pos().unwrap(),
+                Vec3(
+                    bound.max(Axis::X).unwrap(),
+                    bound.max(Axis::Y).unwrap(),
+                    bound.max(Axis::Z).unwrap(),
+                ),
+            );
+            let inside = |p: Vec3<i32>| {
+                [Axis::X, Axis::Y, Axis::Z]
+                    .into_iter()
+                    .all(|a| min.on(a) <= p.on(a) && p.on(a) < max.on(a))
+            };
+            // flood the empty cubes from one layer outside of the bound
+            let start = min - Vec3(1, 1, 1);
+            let mut outside = HashSet::from([start]);
+            let mut stack = vec![start];
+            while let Some(p) = stack.pop() {
+                for n in neighbors(p, Connectivity::Face) {
+                    let in_reach = [Axis::X, Axis::Y, Axis::Z]
+                        .into_iter()
+                        .all(|a| min.on(a) - 1 <= n.on(a) && n.on(a) <= max.on(a));
+                    if in_reach && !set.contains(&n) && outside.insert(n) {
+                        stack.push(n);
+                    }
+                }
+            }
+            let mut expected = BTreeSet::new();
+            let size = max - min;
+            let size = (size.x() as u32, size.y() as u32, size.z() as u32);
+            for p in cubes(&[Geom3::new(min, size)]) {
+                if inside(p) && !set.contains(&p) && !outside.contains(&p) {
+                    expected.insert(p);
+                }
+            }
+            let found = cavities(&prisms).unwrap();
+            assert_disjoint(&found);
+            assert_eq!(cubes(&found), expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn cavities_of_hollow_box() {
+        let mut shell = Arbitrary::from_prism(Geom3::new((0, 0, 0), (4, 4, 4)));
+        shell.difference(&Arbitrary::from_prism(Geom3::new((1, 1, 1), (2, 2, 2))));
+        let found = cavities(&shell.boxes()).unwrap();
+        assert_eq!(cubes(&found), cubes(&[Geom3::new((1, 1, 1), (2, 2, 2))]));
+    }
+
+    #[test]
+    fn components_match_flood_fill() {
+        for connectivity in [Connectivity::Face, Connectivity::Edge, Connectivity::Vertex] {
+            for seed in 0..10 {
+                let prisms = coalesce(&random_prisms(seed, 15, 14)).unwrap();
+                let set = cubes(&prisms);
+                let mut seen = HashSet::new();
+                let mut expected = Vec::new();
+                for start in &set {
+                    if !seen.insert(*start) {
+                        continue;
+                    }
+                    let mut stack = vec![*start];
+                    let mut size = 0;
+                    while let Some(p) = stack.pop() {
+                        size += 1;
+                        for n in neighbors(p, connectivity) {
+                            if set.contains(&n) && seen.insert(n) {
+                                stack.push(n);
+                            }
+                        }
+                    }
+                    expected.push(size);
+                }
+                let mut found: Vec<u64> = components(&prisms, connectivity)
+                    .iter()
+                    .map(|c| cubes(c).len() as u64)
+                    .collect();
+                found.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!(found, expected, "seed {seed} {connectivity:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn voxel_set_matches_cubes() {
+        for seed in 0..10 {
+            let prisms = random_prisms(seed, 20, 40);
+            let set = VoxelSet::from_prisms(&prisms).unwrap();
+            let expected = cubes(&prisms);
+            for p in cubes(&[Geom3::new((-1, -1, -1), (46, 46, 46))]) {
+                assert_eq!(set.contains(p), expected.contains(&p), "seed {seed} {p:?}");
+            }
+            assert_eq!(cubes(&set.into_prisms()), expected);
+        }
+    }
+
+    #[test]
+    fn generators_have_expected_volumes() {
+        let shape = stairs(Vec3(0, 0, 0), Axis::X, false, 3, Vec3(2, 1, 1)).unwrap();
+        assert_eq!(shape.volume(), 2 * (1 + 2 + 3));
+        let shape = pyramid(Geom3::new((0, 0, 0), (5, 5, 1)), 10).unwrap();
+        assert_eq!(shape.volume(), 25 + 9 + 1);
+        let shape = wedge(Geom3::new((0, 0, 0), (4, 1, 4)), Axis::X, false).unwrap();
+        assert_eq!(shape.volume(), 1 + 2 + 3 + 4);
+        let prisms = cylinder(Vec3(0, 0, 0), 2.0, 3, Axis::Z, false).unwrap();
+        assert_eq!(cubes(&prisms).len(), 13 * 3);
+        let prisms = ellipsoid(Vec3(0, 0, 0), Vec3(2.0, 2.0, 2.0)).unwrap();
+        assert_eq!(cubes(&prisms).len(), 33);
+        let prisms = path(&[Vec3(0, 0, 0), Vec3(4, 0, 0)], false, 1).unwrap();
+        assert_eq!(prisms, vec![Geom3::new((0, 0, 0), (5, 1, 1))]);
+        let prisms = heightmap(Vec3(0, 0, 0), &[vec![1, 2], vec![1, 0]]).unwrap();
+        assert_eq!(cubes(&prisms).len(), 4);
+        let rows = ["#.#".to_string(), "###".to_string()];
+        let prisms = extrude(&rows, None, Vec3(0, 0, 0), Axis::Z, 2).unwrap();
+        assert_eq!(cubes(&prisms).len(), 5 * 2);
+        let prisms = text_layers(&[rows.to_vec(), rows.to_vec()], None, Vec3(0, 0, 0)).unwrap();
+        assert_eq!(cubes(&prisms).len(), 5 * 2);
+    }
+
+    #[test]
+    fn generators_out_of_range() {
+        let max = i32::MAX;
+        assert!(stairs(Vec3(max - 2, 0, 0), Axis::X, false, 3, Vec3(1, 1, 1)).is_none());
+        assert!(stairs(Vec3(0, 0, max - 2), Axis::X, false, 3, Vec3(1, 1, 1)).is_none());
+        assert!(pyramid(Geom3::new((0, 0, max - 1), (5, 5, 1)), 3).is_none());
+        assert!(pyramid(Geom3::new((0, 0, max - 3), (5, 5, 1)), 3).is_some());
+        assert!(wedge(Geom3::new((max - 1, 0, 0), (4, 1, 4)), Axis::X, false).is_none());
+        assert!(cylinder(Vec3(0, 0, max - 2), 1.0, 5, Axis::Z, false).is_none());
+        assert!(cylinder(Vec3(0, 0, i32::MIN + 2), 1.0, 5, Axis::Z, true).is_none());
+        assert!(cylinder(Vec3(max, 0, 0), 1.0, 1, Axis::Z, false).is_none());
+        assert!(ellipsoid(Vec3(max - 1, 0, 0), Vec3(2.0, 1.0, 1.0)).is_none());
+        assert!(ellipsoid(Vec3(0, 0, 0), Vec3(1e10, 1.0, 1.0)).is_none());
+        assert!(path(&[Vec3(max - 1, 0, 0), Vec3(max, 0, 0)], false, 3).is_none());
+        assert!(path(&[Vec3(i32::MIN, 0, 0)], false, 3).is_none());
+        assert!(path(&[Vec3(max - 1, 0, 0)], false, 1).is_some());
+        assert!(heightmap(Vec3(max - 2, 0, 0), &[vec![1, 1, 1, 1]]).is_none());
+        assert!(heightmap(Vec3(0, max - 1, 0), &[vec![1], vec![1], vec![1]]).is_none());
+        assert!(heightmap(Vec3(0, 0, max), &[vec![1]]).is_none());
+        let rows = ["##".to_string()];
+        assert!(extrude(&rows, None, Vec3(max - 1, 0, 0), Axis::Z, 1).is_none());
+        assert!(extrude(&rows, None, Vec3(0, 0, max), Axis::Z, 1).is_none());
+        assert!(text_layers(&[rows.to_vec()], None, Vec3(max - 1, 0, 0)).is_none());
+    }
+}